@@ -2,4 +2,21 @@
 pub const ANCHOR_DISCRIMINATOR: usize = 8;
 pub const AD_REQUEST_PDA_SEED: &[u8] = b"ad_request";
 pub const AD_RESPONSE_PDA_SEED: &[u8] = b"ad_response";
-pub const AUCTION_RECORD_PDA_SEED: &[u8] = b"auction_record";
\ No newline at end of file
+pub const AUCTION_RECORD_PDA_SEED: &[u8] = b"auction_record";
+pub const OPERATOR_PDA_SEED: &[u8] = b"operator";
+
+// Fees and revenue shares are expressed in basis points (1/100th of a percent)
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+// Lowest revenue share (in basis points) a publisher can be configured with
+pub const MIN_PUBLISHER_REV_SHARE_BPS: u16 = 5_000;
+
+// Default bounds (in seconds) for the TTL a publisher can give an ad request.
+// Real-time bidding wants expirations of a second or so, sponsorship deals can run for weeks
+pub const DEFAULT_MIN_REQUEST_TTL: i64 = 1;
+pub const DEFAULT_MAX_REQUEST_TTL: i64 = 60 * 60 * 24 * 30;
+
+// Most bids one ad request can hold. process_auction has to take every bid in a single
+// transaction and commits each response, so 20 leaves room for its fixed accounts within
+// the 1232 byte transaction limit
+pub const MAX_BIDS_PER_REQUEST: u32 = 20;
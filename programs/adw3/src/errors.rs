@@ -11,10 +11,10 @@ pub enum AdW3Error {
     #[msg("This auction has already been settled")]
     AuctionAlreadySettled,
 
-    #[msg("Invalid fee percentage. Must be between 0-100")]
+    #[msg("Invalid fee. Must be between 0-10000 basis points")]
     InvalidFeePercentage,
 
-    #[msg("Invalid revenue share. Must be between minimum revenue share and 100")]
+    #[msg("Invalid revenue share. Must be between minimum revenue share and 10000 basis points")]
     InvalidRevenueShare,
 
     #[msg("Publisher revenue share below program minimum")]
@@ -69,4 +69,61 @@ pub enum AdW3Error {
     #[msg("There is an overflow somewhere")]
     Overflow,
 
+    #[msg("This bid is not eligible for a refund")]
+    BidNotRefundable,
+
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+
+    #[msg("There is no pending authority transfer")]
+    NoPendingAuthority,
+
+    #[msg("The protocol config is already on the current layout")]
+    ConfigAlreadyMigrated,
+
+    #[msg("Request TTL is outside the configured bounds")]
+    InvalidRequestTtl,
+
+    #[msg("Request TTL bounds must be positive with min not above max")]
+    InvalidTtlBounds,
+
+    #[msg("The request has not expired yet")]
+    RequestNotExpired,
+
+    #[msg("Bid accounts must be response and DSP pairs for this request")]
+    InvalidBidAccounts,
+
+    #[msg("Every bid placed on the request must be supplied")]
+    MissingBids,
+
+    #[msg("The ad request already holds the maximum number of bids")]
+    TooManyBids,
+
+    #[msg("This auction has not been settled yet")]
+    AuctionNotSettled,
+
+    #[msg("This bid can no longer be changed")]
+    BidNotModifiable,
+
+    #[msg("The auction record has already been delegated")]
+    AuctionAlreadyDelegated,
+
+    #[msg("The auction record is not delegated and awaiting the auction")]
+    AuctionNotDelegated,
+
+    #[msg("The auction has already been cleared")]
+    AuctionAlreadyCleared,
+
+    #[msg("The auction has not been cleared yet")]
+    AuctionNotCleared,
+
+    #[msg("Auction results have not been processed yet")]
+    AuctionNotAccounted,
+
+    #[msg("The auction ended without a winner, there is nothing to settle")]
+    NothingToSettle,
+
+    #[msg("Batch accounts must be auction, publisher and DSP triples followed by publisher token accounts")]
+    InvalidBatchAccounts,
+
 }
\ No newline at end of file
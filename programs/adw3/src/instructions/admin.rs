@@ -0,0 +1,496 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{
+        Token,
+        TokenAccount,
+        Transfer,
+        transfer
+    }
+};
+
+use crate::constants::{ANCHOR_DISCRIMINATOR, DEFAULT_MAX_REQUEST_TTL, DEFAULT_MIN_REQUEST_TTL, OPERATOR_PDA_SEED};
+use crate::errors::AdW3Error;
+use crate::state::{
+    AuthorityProposalCancelled, AuthorityProposed, AuthorityTransferred, ConfigUpdated, ExchangeVault,
+    LegacyProtocolConfig, Operator, OperatorAdded, OperatorRemoved, PlatformFeesWithdrawn, ProtocolConfig,
+    ProtocolPauseUpdated, RemainderPolicy, DSP,
+};
+
+// Halt or resume the protocol, only the config authority can do this
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+}
+
+impl<'info> SetPaused<'info> {
+    pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+        self.adw_config.is_paused = paused;
+
+        emit!(ProtocolPauseUpdated {
+            authority: self.authority.key(),
+            is_paused: paused,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Change the fee split after initialize, only the config authority can do this
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+}
+
+impl<'info> UpdateProtocolConfig<'info> {
+    pub fn update(
+        &mut self,
+        platform_fee_bps: u16,
+        publisher_rev_share_bps: u16,
+        remainder_policy: RemainderPolicy,
+        min_request_ttl: i64,
+        max_request_ttl: i64,
+    ) -> Result<()> {
+        ProtocolConfig::validate_fees(platform_fee_bps, publisher_rev_share_bps)?;
+        ProtocolConfig::validate_ttl_bounds(min_request_ttl, max_request_ttl)?;
+
+        let old_platform_fee_bps = self.adw_config.platform_fee_bps;
+        let old_publisher_rev_share_bps = self.adw_config.publisher_rev_share_bps;
+        let old_remainder_policy = self.adw_config.remainder_policy;
+        let old_min_request_ttl = self.adw_config.min_request_ttl;
+        let old_max_request_ttl = self.adw_config.max_request_ttl;
+
+        self.adw_config.platform_fee_bps = platform_fee_bps;
+        self.adw_config.publisher_rev_share_bps = publisher_rev_share_bps;
+        self.adw_config.remainder_policy = remainder_policy;
+        self.adw_config.min_request_ttl = min_request_ttl;
+        self.adw_config.max_request_ttl = max_request_ttl;
+
+        emit!(ConfigUpdated {
+            authority: self.authority.key(),
+            old_platform_fee_bps,
+            new_platform_fee_bps: platform_fee_bps,
+            old_publisher_rev_share_bps,
+            new_publisher_rev_share_bps: publisher_rev_share_bps,
+            old_remainder_policy,
+            new_remainder_policy: remainder_policy,
+            old_min_request_ttl,
+            new_min_request_ttl: min_request_ttl,
+            old_max_request_ttl,
+            new_max_request_ttl: max_request_ttl,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// One-off upgrade of protocol accounts stored on an older layout: the config with
+// whole-percentage fees, the vault state without a reserve balance and DSPs without
+// locked_amount / total_spent. Bids escrowed in the vault by the old place_ad_bid are
+// not touched here, each DSP gets them back with refund_legacy_bid
+#[derive(Accounts)]
+pub struct MigrateProtocolConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: may still be on the legacy layout so it can't be loaded as ProtocolConfig, checked in migrate
+    #[account(
+        mut,
+        seeds = [b"adw3_config"],
+        bump,
+    )]
+    pub adw_config: UncheckedAccount<'info>,
+
+    /// CHECK: may still be on the legacy layout so it can't be loaded as ExchangeVault, checked in migrate
+    #[account(
+        mut,
+        seeds = [b"adw3_vault"],
+        bump,
+    )]
+    pub exchange_vault_state: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigrateProtocolConfig<'info> {
+    pub fn migrate(&mut self, dsp_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let config_info = self.adw_config.to_account_info();
+        let vault_info = self.exchange_vault_state.to_account_info();
+
+        let config_len = ANCHOR_DISCRIMINATOR + ProtocolConfig::INIT_SPACE;
+        let vault_len = ANCHOR_DISCRIMINATOR + ExchangeVault::INIT_SPACE;
+        let config_is_legacy = config_info.data_len() == ANCHOR_DISCRIMINATOR + LegacyProtocolConfig::SPACE;
+        let vault_is_legacy = vault_info.data_len() < vault_len;
+
+        require!(
+            config_is_legacy || vault_is_legacy || !dsp_accounts.is_empty(),
+            AdW3Error::ConfigAlreadyMigrated
+        );
+
+        if config_is_legacy {
+            let legacy = {
+                let data = config_info.try_borrow_data()?;
+                require!(
+                    data[..ANCHOR_DISCRIMINATOR] == *ProtocolConfig::DISCRIMINATOR,
+                    ErrorCode::AccountDiscriminatorMismatch
+                );
+                LegacyProtocolConfig::deserialize(&mut &data[ANCHOR_DISCRIMINATOR..])?
+            };
+
+            require_keys_eq!(legacy.authority, self.authority.key(), AdW3Error::UnauthorizedAccess);
+
+            self.grow(&config_info, config_len)?;
+
+            let config = ProtocolConfig {
+                authority: legacy.authority,
+                pending_authority: None,
+                platform_fee_bps: legacy.platform_fee_percentage as u16 * 100,
+                publisher_rev_share_bps: legacy.publisher_rev_share as u16 * 100,
+                remainder_policy: RemainderPolicy::ProtocolReserve,
+                min_request_ttl: DEFAULT_MIN_REQUEST_TTL,
+                max_request_ttl: DEFAULT_MAX_REQUEST_TTL,
+                is_paused: legacy.is_paused,
+                token_mint: legacy.token_mint,
+                bump: legacy.bump,
+            };
+
+            let mut data = config_info.try_borrow_mut_data()?;
+            config.try_serialize(&mut &mut data[..])?;
+        } else {
+            let config = ProtocolConfig::try_deserialize(&mut &config_info.try_borrow_data()?[..])?;
+            require_keys_eq!(config.authority, self.authority.key(), AdW3Error::UnauthorizedAccess);
+        }
+
+        if vault_is_legacy {
+            {
+                let data = vault_info.try_borrow_data()?;
+                require!(
+                    data[..ANCHOR_DISCRIMINATOR] == *ExchangeVault::DISCRIMINATOR,
+                    ErrorCode::AccountDiscriminatorMismatch
+                );
+            }
+
+            // reserve_balance is the last field, so the zero-filled tail is its initial value
+            self.grow(&vault_info, vault_len)?;
+        }
+
+        // locked_amount and total_spent are the last DSP fields and both start at zero
+        let dsp_len = ANCHOR_DISCRIMINATOR + DSP::INIT_SPACE;
+        for dsp_info in dsp_accounts {
+            require_keys_eq!(*dsp_info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+            require!(dsp_info.is_writable, ErrorCode::AccountNotMutable);
+            require!(dsp_info.data_len() < dsp_len, AdW3Error::ConfigAlreadyMigrated);
+            {
+                let data = dsp_info.try_borrow_data()?;
+                require!(
+                    data[..ANCHOR_DISCRIMINATOR] == *DSP::DISCRIMINATOR,
+                    ErrorCode::AccountDiscriminatorMismatch
+                );
+            }
+
+            self.grow(dsp_info, dsp_len)?;
+        }
+
+        Ok(())
+    }
+
+    // Grow the account and top up rent for the new layout
+    fn grow(&self, account: &AccountInfo<'info>, new_len: usize) -> Result<()> {
+        let rent_due = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(account.lamports());
+
+        if rent_due > 0 {
+            let cpi_accounts = system_program::Transfer {
+                from: self.authority.to_account_info(),
+                to: account.clone(),
+            };
+
+            system_program::transfer(
+                CpiContext::new(self.system_program.to_account_info(), cpi_accounts),
+                rent_due,
+            )?;
+        }
+
+        account.realloc(new_len, true)?;
+
+        Ok(())
+    }
+}
+
+// First step of an authority rotation, the current authority nominates its successor
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+}
+
+impl<'info> ProposeAuthority<'info> {
+    pub fn propose(&mut self, new_authority: Pubkey) -> Result<()> {
+        self.adw_config.pending_authority = Some(new_authority);
+
+        emit!(AuthorityProposed {
+            authority: self.authority.key(),
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Drop a pending proposal before it is accepted
+#[derive(Accounts)]
+pub struct CancelAuthorityProposal<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+}
+
+impl<'info> CancelAuthorityProposal<'info> {
+    pub fn cancel(&mut self) -> Result<()> {
+        let pending_authority = self.adw_config.pending_authority
+            .take()
+            .ok_or(AdW3Error::NoPendingAuthority)?;
+
+        emit!(AuthorityProposalCancelled {
+            authority: self.authority.key(),
+            pending_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Second step of an authority rotation, the nominee signs to take over both the config and the vault
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_vault"],
+        bump = exchange_vault_state.bump,
+    )]
+    pub exchange_vault_state: Account<'info, ExchangeVault>,
+}
+
+impl<'info> AcceptAuthority<'info> {
+    pub fn accept(&mut self) -> Result<()> {
+        let pending_authority = self.adw_config.pending_authority
+            .ok_or(AdW3Error::NoPendingAuthority)?;
+        require_keys_eq!(pending_authority, self.new_authority.key(), AdW3Error::UnauthorizedAccess);
+
+        let old_authority = self.adw_config.authority;
+
+        self.adw_config.authority = pending_authority;
+        self.adw_config.pending_authority = None;
+        self.exchange_vault_state.authority = pending_authority;
+
+        emit!(AuthorityTransferred {
+            old_authority,
+            new_authority: pending_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Move collected platform fees out of the exchange vault to a treasury
+#[derive(Accounts)]
+pub struct WithdrawPlatformFees<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_vault"],
+        bump = exchange_vault_state.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub exchange_vault_state: Account<'info, ExchangeVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = exchange_vault_state.token_mint,
+        associated_token::authority = exchange_vault_state,
+    )]
+    pub exchange_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: treasury wallet chosen by the authority, only used to derive its token account
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = exchange_vault_state.token_mint,
+        associated_token::authority = treasury,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> WithdrawPlatformFees<'info> {
+    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+        require!(amount > 0, AdW3Error::InvalidAmount);
+        require!(amount <= self.exchange_vault_state.fee_balance, AdW3Error::ExcessiveWithdrawalAmount);
+
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = Transfer {
+            from: self.exchange_vault.to_account_info(),
+            to: self.treasury_token_account.to_account_info(),
+            authority: self.exchange_vault_state.to_account_info(),
+        };
+
+        // Need vault state seeds for signing
+        let vault_seeds = &[
+            b"adw3_vault".as_ref(),
+            &[self.exchange_vault_state.bump]
+        ];
+
+        let vault_signer = &[&vault_seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+
+        transfer(cpi_ctx, amount)?;
+
+        self.exchange_vault_state.fee_balance -= amount;
+
+        self.exchange_vault_state.total_balance = self.exchange_vault_state.total_balance
+            .checked_sub(amount)
+            .ok_or(AdW3Error::Overflow)?;
+
+        emit!(PlatformFeesWithdrawn {
+            authority: self.authority.key(),
+            treasury: self.treasury.key(),
+            amount,
+            fee_balance: self.exchange_vault_state.fee_balance,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Approve a key to crank process_auction, process_auction_results and settle_auction
+#[derive(Accounts)]
+#[instruction(operator: Pubkey)]
+pub struct AddOperator<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = ANCHOR_DISCRIMINATOR + Operator::INIT_SPACE,
+        seeds = [OPERATOR_PDA_SEED, operator.as_ref()],
+        bump,
+    )]
+    pub operator_state: Account<'info, Operator>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> AddOperator<'info> {
+    pub fn add(&mut self, operator: Pubkey, bumps: AddOperatorBumps) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+
+        self.operator_state.set_inner(Operator {
+            operator,
+            added_at: timestamp,
+            bump: bumps.operator_state,
+        });
+
+        emit!(OperatorAdded {
+            authority: self.authority.key(),
+            operator,
+            timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Revoke an operator, its account rent goes back to the authority
+#[derive(Accounts)]
+#[instruction(operator: Pubkey)]
+pub struct RemoveOperator<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"adw3_config"],
+        bump = adw_config.bump,
+        has_one = authority @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [OPERATOR_PDA_SEED, operator.as_ref()],
+        bump = operator_state.bump,
+    )]
+    pub operator_state: Account<'info, Operator>,
+}
+
+impl<'info> RemoveOperator<'info> {
+    pub fn remove(&mut self, operator: Pubkey) -> Result<()> {
+        emit!(OperatorRemoved {
+            authority: self.authority.key(),
+            operator,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
@@ -1,19 +1,14 @@
 use anchor_lang::prelude::*;
-use crate::constants::{ANCHOR_DISCRIMINATOR, AUCTION_RECORD_PDA_SEED};
+use crate::constants::{
+  ANCHOR_DISCRIMINATOR, AD_REQUEST_PDA_SEED, AD_RESPONSE_PDA_SEED, AUCTION_RECORD_PDA_SEED, MAX_BIDS_PER_REQUEST,
+};
 use crate::state::{
-  AdRequest, AdResponse, ExchangeVault, ProtocolConfig, Publisher, RequestStatus, ResponseStatus, DSP, AuctionRecord, 
+  AdRequest, AdResponse, AuctionState, AuctionType, ProtocolConfig, Publisher, RemainderPolicy, RequestStatus, ResponseStatus,
+  DSP, AuctionRecord,
+  AdRequestCancelled, BidRefunded, BidUpdated,
 };
 use crate::errors::AdW3Error;
-
-use anchor_spl::{
-  associated_token::AssociatedToken,
-  token::{
-      Token,
-      TokenAccount,
-      Transfer,
-      transfer
-  }
-};
+use crate::instructions::cleanup::release_bids;
 
 // the instructions module contains the logic for the auction program
 //place bid instruction
@@ -64,11 +59,19 @@ impl <'info> PlaceAsk<'info> {
         &mut self,
         ad_request_id: [u8; 32],
         ad_floor_price: u64,
+        ttl_seconds: i64,
+        auction_type: AuctionType,
         bumps: PlaceAskBumps
     ) -> Result<()> {
         // Check if protocol is paused
         require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
 
+        // Request lifetime must fall within the protocol's bounds
+        require!(
+            ttl_seconds >= self.adw_config.min_request_ttl && ttl_seconds <= self.adw_config.max_request_ttl,
+            AdW3Error::InvalidRequestTtl
+        );
+
         // Create a unique auction ID
         let auction_id_str = format!("auction-{}", hex::encode(&ad_request_id[0..8]));
         let mut auction_id = [0u8; 32];
@@ -81,7 +84,9 @@ impl <'info> PlaceAsk<'info> {
             publisher: self.publisher.key(),
             request_id: ad_request_id,
             floor_price: ad_floor_price,
-            expiration: Clock::get()?.unix_timestamp + 60 * 60 * 12, // 12 hours expiration
+            expiration: Clock::get()?.unix_timestamp + ttl_seconds,
+            auction_type,
+            bid_count: 0,
             status: RequestStatus::Open,
             bump: bumps.ad_request,
         });
@@ -95,8 +100,11 @@ impl <'info> PlaceAsk<'info> {
             clearing_price : 0,
             publisher_payment : 0,
             platform_fee : 0,
+            remainder: 0,
+            remainder_policy: RemainderPolicy::ProtocolReserve, // placeholder, process_auction_results sets it from the config
+            auction_type,
             timestamp: 0,
-            is_settled: false,
+            auction_state: AuctionState::Created,
             bump: bumps.auction_record,
         }
 
@@ -107,7 +115,7 @@ impl <'info> PlaceAsk<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(creative_id: [u8; 32])]
+#[instruction(ad_request_id: [u8; 32], bid_amount: u64, creative_id: [u8; 32])]
 pub struct PlaceBid<'info> {
   #[account(mut)]
   pub dsp: Signer<'info>,
@@ -122,43 +130,29 @@ pub struct PlaceBid<'info> {
     init,
     payer = dsp,
     space = ANCHOR_DISCRIMINATOR + AdResponse::INIT_SPACE,
-    seeds = [b"ad_response", dsp.key().as_ref(), &creative_id],
+    seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
     bump,
   )]
   pub ad_dsp_response: Account<'info, AdResponse>,
 
+  // the request being bid on, must still be open and not yet delegated to the rollup
+  #[account(
+    mut,
+    seeds = [AD_REQUEST_PDA_SEED, ad_request.publisher.as_ref(), &ad_request_id],
+    bump = ad_request.bump,
+    constraint = ad_request.status == RequestStatus::Open @ AdW3Error::RequestClosed,
+    constraint = Clock::get()?.unix_timestamp < ad_request.expiration @ AdW3Error::RequestExpired,
+  )]
+  pub ad_request: Account<'info, AdRequest>,
+
   // config accounts
   #[account(
     seeds = [b"adw3_config"],
     bump,
 )]
 pub adw_config: Account<'info, ProtocolConfig>,
-// need to load vault here.
-#[account(
-    mut,
-    seeds = [b"adw3_vault"],
-    bump,
-)]
-pub exchange_vault_state: Account<'info, ExchangeVault>,
 
-#[account(
-    mut,
-    associated_token::mint = exchange_vault_state.token_mint,
-    associated_token::authority = exchange_vault_state,
-)]
-pub exchange_vault: Account<'info, TokenAccount>,
-
-  // DSP's token account
-  #[account(
-    mut,
-    associated_token::mint = exchange_vault_state.token_mint,
-    associated_token::authority = dsp,
-  )]
-  pub dsp_token_account: Account<'info, TokenAccount>,
-
-pub token_program: Program<'info, Token>,
 pub system_program: Program<'info, System>,
-pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 
@@ -171,21 +165,15 @@ impl <'info> PlaceBid<'info> {
     bumps: PlaceBidBumps
   ) -> Result<()> {
     // Check string lengths individually
-    require!(self.adw_config.is_paused == false, AdW3Error::ProtocolPaused);
+    require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
 
-    // start with cpi call to deposit the bid amount into the exchange vault
+    require!(bid_amount >= self.ad_request.floor_price, AdW3Error::BidBelowFloorPrice);
 
-    let cpi_program = self.token_program.to_account_info();
-    
-    let cpi_accounts = Transfer{
-      from: self.dsp_token_account.to_account_info(),
-      to:  self.exchange_vault.to_account_info(),
-      authority:  self.dsp.to_account_info(),
-    };
+    // every bid has to fit in the one process_auction transaction
+    require!(self.ad_request.bid_count < MAX_BIDS_PER_REQUEST, AdW3Error::TooManyBids);
 
-    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-
-    transfer(cpi_ctx, bid_amount)?;
+    // lock the bid against the DSP's prepaid balance held in the exchange vault
+    require!(bid_amount <= self.dsp_state.available_balance(), AdW3Error::InsufficientFunds);
 
     // Initialize ad request state
     self.ad_dsp_response.set_inner(AdResponse {
@@ -196,11 +184,242 @@ impl <'info> PlaceBid<'info> {
       created_at: Clock::get()?.unix_timestamp,
       status: ResponseStatus::Submitted,
       bump: bumps.ad_dsp_response,
+      ad_request: self.ad_request.key(),
+    });
+
+    self.dsp_state.locked_amount = self.dsp_state.locked_amount
+      .checked_add(bid_amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    self.ad_request.bid_count = self.ad_request.bid_count
+      .checked_add(1)
+      .ok_or(AdW3Error::Overflow)?;
+
+    Ok(())
+  }
+}
+
+
+// Raise or lower a bid while the request is still open, the escrow follows the new amount
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32], creative_id: [u8; 32])]
+pub struct UpdateBid<'info> {
+  pub dsp: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"dsp", dsp.key().as_ref()],
+    bump = dsp_state.bump,
+  )]
+  pub dsp_state: Account<'info, DSP>,
+
+  #[account(
+    mut,
+    seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
+    bump = ad_response.bump,
+    constraint = ad_response.status == ResponseStatus::Submitted @ AdW3Error::BidNotModifiable,
+  )]
+  pub ad_response: Account<'info, AdResponse>,
+
+  #[account(
+    seeds = [AD_REQUEST_PDA_SEED, ad_request.publisher.as_ref(), &ad_request_id],
+    bump = ad_request.bump,
+    constraint = ad_request.status == RequestStatus::Open @ AdW3Error::RequestClosed,
+    constraint = Clock::get()?.unix_timestamp < ad_request.expiration @ AdW3Error::RequestExpired,
+    constraint = ad_response.ad_request == ad_request.key() @ AdW3Error::InvalidBidAccounts,
+  )]
+  pub ad_request: Account<'info, AdRequest>,
+
+  #[account(
+    seeds = [b"adw3_config"],
+    bump,
+  )]
+  pub adw_config: Account<'info, ProtocolConfig>,
+}
+
+impl <'info> UpdateBid<'info> {
+  pub fn update_bid(&mut self, new_bid_amount: u64) -> Result<()> {
+    require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
+    require!(new_bid_amount >= self.ad_request.floor_price, AdW3Error::BidBelowFloorPrice);
+
+    let old_bid_amount = self.ad_response.bid_amount;
+
+    if new_bid_amount > old_bid_amount {
+      let increase = new_bid_amount - old_bid_amount;
+      require!(increase <= self.dsp_state.available_balance(), AdW3Error::InsufficientFunds);
+
+      self.dsp_state.locked_amount = self.dsp_state.locked_amount
+        .checked_add(increase)
+        .ok_or(AdW3Error::Overflow)?;
+    } else {
+      self.dsp_state.locked_amount = self.dsp_state.locked_amount
+        .checked_sub(old_bid_amount - new_bid_amount)
+        .ok_or(AdW3Error::Overflow)?;
+    }
+
+    // an amended bid queues behind bids already standing at the same amount
+    self.ad_response.bid_amount = new_bid_amount;
+    self.ad_response.created_at = Clock::get()?.unix_timestamp;
+
+    emit!(BidUpdated {
+      request_id: self.ad_request.request_id,
+      dsp: self.dsp.key(),
+      old_amount: old_bid_amount,
+      new_amount: new_bid_amount,
+      timestamp: self.ad_response.created_at,
+    });
+
+    Ok(())
+  }
+}
+
+// Pull a bid out before the auction runs, unlocking its escrow and closing the response
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32], creative_id: [u8; 32])]
+pub struct RetractBid<'info> {
+  #[account(mut)]
+  pub dsp: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"dsp", dsp.key().as_ref()],
+    bump = dsp_state.bump,
+  )]
+  pub dsp_state: Account<'info, DSP>,
+
+  #[account(
+    mut,
+    close = dsp,
+    seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
+    bump = ad_response.bump,
+    constraint = ad_response.status == ResponseStatus::Submitted @ AdW3Error::BidNotModifiable,
+  )]
+  pub ad_response: Account<'info, AdResponse>,
+
+  #[account(
+    mut,
+    seeds = [AD_REQUEST_PDA_SEED, ad_request.publisher.as_ref(), &ad_request_id],
+    bump = ad_request.bump,
+    constraint = ad_request.status == RequestStatus::Open @ AdW3Error::RequestClosed,
+    constraint = ad_response.ad_request == ad_request.key() @ AdW3Error::InvalidBidAccounts,
+  )]
+  pub ad_request: Account<'info, AdRequest>,
+}
+
+impl <'info> RetractBid<'info> {
+  pub fn retract(&mut self) -> Result<()> {
+    let amount = self.ad_response.bid_amount;
+
+    self.dsp_state.locked_amount = self.dsp_state.locked_amount
+      .checked_sub(amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    self.ad_request.bid_count = self.ad_request.bid_count
+      .checked_sub(1)
+      .ok_or(AdW3Error::Overflow)?;
+
+    emit!(BidRefunded {
+      request_id: self.ad_request.request_id,
+      dsp: self.dsp.key(),
+      amount,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+  }
+}
+
+// Refund a losing bid once the auction has been processed and the response undelegated
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32], creative_id: [u8; 32])]
+pub struct RefundLosingBid<'info> {
+  #[account(mut)]
+  pub dsp: Signer<'info>,
+
+  // the response is closed and its rent returned to the DSP
+  #[account(
+    mut,
+    close = dsp,
+    seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
+    bump = ad_response.bump,
+    constraint = ad_response.status == ResponseStatus::Loss @ AdW3Error::BidNotRefundable,
+  )]
+  pub ad_response: Account<'info, AdResponse>,
+
+  #[account(
+    mut,
+    seeds = [b"dsp", dsp.key().as_ref()],
+    bump = dsp_state.bump,
+  )]
+  pub dsp_state: Account<'info, DSP>,
+}
+
+impl <'info> RefundLosingBid<'info> {
+  pub fn refund(&mut self) -> Result<()> {
+    let amount = self.ad_response.bid_amount;
+
+    // Unlock the escrowed bid in the DSP's prepaid balance
+    self.dsp_state.locked_amount = self.dsp_state.locked_amount
+      .checked_sub(amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    emit!(BidRefunded {
+      request_id: self.ad_response.request_id,
+      dsp: self.dsp.key(),
+      amount,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+  }
+}
+
+// Withdraw an open ask before the auction runs, bids are passed as (ad_response, dsp_state) pairs
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32])]
+pub struct CancelAsk<'info> {
+  #[account(mut)]
+  pub publisher: Signer<'info>,
+
+  // a delegated request is owned by the delegation program and fails to load here
+  #[account(
+    mut,
+    close = publisher,
+    seeds = [AD_REQUEST_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
+    bump = ad_request.bump,
+    constraint = ad_request.status == RequestStatus::Open @ AdW3Error::RequestClosed,
+  )]
+  pub ad_request: Account<'info, AdRequest>,
+
+  #[account(
+    mut,
+    close = publisher,
+    seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
+    bump = auction_record.bump,
+    constraint = matches!(auction_record.auction_state, AuctionState::Created | AuctionState::Delegated)
+      @ AdW3Error::AuctionAlreadyCleared,
+  )]
+  pub auction_record: Account<'info, AuctionRecord>,
+}
+
+impl <'info> CancelAsk<'info> {
+  pub fn cancel(
+    &mut self,
+    ad_request_id: [u8; 32],
+    bid_accounts: &'info [AccountInfo<'info>],
+  ) -> Result<()> {
+    self.ad_request.status = RequestStatus::Cancelled;
+
+    let bids_refunded = release_bids(self.ad_request.key(), ad_request_id, bid_accounts)?;
+    require!(bids_refunded == self.ad_request.bid_count, AdW3Error::MissingBids);
+
+    emit!(AdRequestCancelled {
+      request_id: ad_request_id,
+      publisher: self.publisher.key(),
+      bids_refunded,
+      timestamp: Clock::get()?.unix_timestamp,
     });
 
-    // self.dsp_state.locked_amount += bid_amount;
-    
-    self.exchange_vault_state.total_balance += bid_amount;
     Ok(())
   }
 }
@@ -0,0 +1,187 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::{AD_REQUEST_PDA_SEED, AD_RESPONSE_PDA_SEED, AUCTION_RECORD_PDA_SEED};
+use crate::errors::AdW3Error;
+use crate::state::{
+  AdRequest, AdResponse, AuctionRecord, AuctionState, RequestStatus, ResponseStatus, DSP,
+  AdRequestExpired, AuctionArchived, BidRefunded,
+};
+
+// Unlock every bid on ad_request passed in remaining_accounts as (ad_response, dsp_state) pairs.
+// Responses are marked Refunded so they can't be released twice, returns how many were released
+// is_multiple_of needs rustc 1.87, which older SBF platform-tools releases predate
+#[allow(clippy::manual_is_multiple_of)]
+pub fn release_bids<'info>(
+  ad_request: Pubkey,
+  ad_request_id: [u8; 32],
+  bid_accounts: &'info [AccountInfo<'info>],
+) -> Result<u32> {
+  require!(bid_accounts.len() % 2 == 0, AdW3Error::InvalidBidAccounts);
+
+  let mut released = 0;
+
+  for pair in bid_accounts.chunks(2) {
+    let mut response = Account::<AdResponse>::try_from(&pair[0])?;
+    let mut dsp_state = Account::<DSP>::try_from(&pair[1])?;
+
+    require_keys_eq!(response.ad_request, ad_request, AdW3Error::InvalidBidAccounts);
+    require!(response.status == ResponseStatus::Submitted, AdW3Error::BidNotRefundable);
+    require_keys_eq!(dsp_state.authority, response.dsp, AdW3Error::InvalidDSP);
+
+    dsp_state.locked_amount = dsp_state.locked_amount
+      .checked_sub(response.bid_amount)
+      .ok_or(AdW3Error::Overflow)?;
+    response.status = ResponseStatus::Refunded;
+
+    // Write back now, the same DSP may show up again in a later pair
+    dsp_state.exit(&crate::ID)?;
+    response.exit(&crate::ID)?;
+
+    emit!(BidRefunded {
+      request_id: ad_request_id,
+      dsp: response.dsp,
+      amount: response.bid_amount,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    released += 1;
+  }
+
+  Ok(released)
+}
+
+// Permissionless cleanup of a request that expired before its auction ran
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32])]
+pub struct ExpireAdRequest<'info> {
+  pub caller: Signer<'info>,
+
+  /// CHECK: publisher wallet that paid for the request, only receives the rent back
+  #[account(mut)]
+  pub publisher: UncheckedAccount<'info>,
+
+  #[account(
+    mut,
+    close = publisher,
+    seeds = [AD_REQUEST_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
+    bump = ad_request.bump,
+    constraint = ad_request.status == RequestStatus::Open @ AdW3Error::RequestClosed,
+    constraint = Clock::get()?.unix_timestamp >= ad_request.expiration @ AdW3Error::RequestNotExpired,
+  )]
+  pub ad_request: Account<'info, AdRequest>,
+
+  #[account(
+    mut,
+    close = publisher,
+    seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
+    bump = auction_record.bump,
+    constraint = matches!(auction_record.auction_state, AuctionState::Created | AuctionState::Delegated)
+      @ AdW3Error::AuctionAlreadyCleared,
+  )]
+  pub auction_record: Account<'info, AuctionRecord>,
+}
+
+impl<'info> ExpireAdRequest<'info> {
+  pub fn expire(
+    &mut self,
+    ad_request_id: [u8; 32],
+    bid_accounts: &'info [AccountInfo<'info>],
+  ) -> Result<()> {
+    self.ad_request.status = RequestStatus::Expired;
+
+    let bids_refunded = release_bids(self.ad_request.key(), ad_request_id, bid_accounts)?;
+    // every escrowed bid has to be released before the request is closed
+    require!(bids_refunded == self.ad_request.bid_count, AdW3Error::MissingBids);
+
+    emit!(AdRequestExpired {
+      request_id: ad_request_id,
+      publisher: self.publisher.key(),
+      bids_refunded,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+  }
+}
+
+// Reclaim rent once an auction is settled or went unfilled. The request and record go
+// back to the publisher, the winning response (when there is one) back to the winning DSP
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32])]
+pub struct CloseSettledAuction<'info> {
+  #[account(mut)]
+  pub publisher: Signer<'info>,
+
+  #[account(
+    mut,
+    close = publisher,
+    seeds = [AD_REQUEST_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
+    bump = ad_request.bump,
+  )]
+  pub ad_request: Account<'info, AdRequest>,
+
+  #[account(
+    mut,
+    close = publisher,
+    seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
+    bump = auction_record.bump,
+    constraint = matches!(auction_record.auction_state, AuctionState::Settled | AuctionState::NoFill)
+      @ AdW3Error::AuctionNotSettled,
+  )]
+  pub auction_record: Account<'info, AuctionRecord>,
+
+  /// CHECK: winning DSP wallet, checked against the auction record
+  #[account(mut)]
+  pub winning_dsp: Option<UncheckedAccount<'info>>,
+
+  #[account(mut)]
+  pub winning_response: Option<Account<'info, AdResponse>>,
+}
+
+impl<'info> CloseSettledAuction<'info> {
+  pub fn close(&mut self, ad_request_id: [u8; 32]) -> Result<()> {
+    // Emit the outcome first so indexers keep it once the accounts are gone
+    emit!(AuctionArchived {
+      request_id: ad_request_id,
+      publisher: self.publisher.key(),
+      winning_dsp: self.auction_record.winning_dsp,
+      bid_amount: self.auction_record.bid_amount,
+      clearing_price: self.auction_record.clearing_price,
+      publisher_payment: self.auction_record.publisher_payment,
+      platform_fee: self.auction_record.platform_fee,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    match (self.auction_record.winning_dsp, &self.winning_dsp, &self.winning_response) {
+      (Some(winner), Some(winning_dsp), Some(winning_response)) => {
+        require_keys_eq!(winning_dsp.key(), winner, AdW3Error::InvalidDSP);
+        require_keys_eq!(winning_response.dsp, winner, AdW3Error::InvalidDSP);
+        require_keys_eq!(winning_response.ad_request, self.ad_request.key(), AdW3Error::InvalidAuctionId);
+        require!(winning_response.status == ResponseStatus::Win, AdW3Error::InvalidDSP);
+
+        winning_response.close(winning_dsp.to_account_info())?;
+      }
+      (None, None, None) => {}
+      _ => return err!(AdW3Error::InvalidDSP),
+    }
+
+    Ok(())
+  }
+}
+
+// Let a DSP reclaim rent on a response whose escrow was released without an auction
+#[derive(Accounts)]
+#[instruction(ad_request_id: [u8; 32], creative_id: [u8; 32])]
+pub struct CloseAdResponse<'info> {
+  #[account(mut)]
+  pub dsp: Signer<'info>,
+
+  #[account(
+    mut,
+    close = dsp,
+    seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
+    bump = ad_response.bump,
+    constraint = ad_response.status == ResponseStatus::Refunded @ AdW3Error::BidNotRefundable,
+  )]
+  pub ad_response: Account<'info, AdResponse>,
+}
@@ -0,0 +1,298 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+  associated_token::AssociatedToken,
+  token::{
+      Token,
+      TokenAccount,
+      Transfer,
+      transfer
+  }
+};
+
+use crate::constants::{ANCHOR_DISCRIMINATOR, AD_RESPONSE_PDA_SEED};
+use crate::errors::AdW3Error;
+use crate::state::{
+  AdResponse, ExchangeVault, LegacyAdResponse, ProtocolConfig, ResponseStatus, DSP,
+  BidRefunded, DspFundsDeposited, DspFundsWithdrawn,
+};
+
+// Move tokens from the DSP's token account into its prepaid balance in the vault
+#[derive(Accounts)]
+pub struct DepositDspFunds<'info> {
+  #[account(mut)]
+  pub dsp: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"dsp", dsp.key().as_ref()],
+    bump = dsp_state.bump,
+  )]
+  pub dsp_state: Account<'info, DSP>,
+
+  #[account(
+    seeds = [b"adw3_config"],
+    bump,
+  )]
+  pub adw_config: Account<'info, ProtocolConfig>,
+
+  #[account(
+    mut,
+    seeds = [b"adw3_vault"],
+    bump = exchange_vault_state.bump,
+  )]
+  pub exchange_vault_state: Account<'info, ExchangeVault>,
+
+  #[account(
+    mut,
+    associated_token::mint = exchange_vault_state.token_mint,
+    associated_token::authority = exchange_vault_state,
+  )]
+  pub exchange_vault: Account<'info, TokenAccount>,
+
+  // DSP's token account
+  #[account(
+    mut,
+    associated_token::mint = exchange_vault_state.token_mint,
+    associated_token::authority = dsp,
+  )]
+  pub dsp_token_account: Account<'info, TokenAccount>,
+
+  pub token_program: Program<'info, Token>,
+  pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> DepositDspFunds<'info> {
+  pub fn deposit(&mut self, amount: u64) -> Result<()> {
+    require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
+    require!(amount > 0, AdW3Error::InvalidAmount);
+
+    let cpi_program = self.token_program.to_account_info();
+
+    let cpi_accounts = Transfer {
+      from: self.dsp_token_account.to_account_info(),
+      to: self.exchange_vault.to_account_info(),
+      authority: self.dsp.to_account_info(),
+    };
+
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+    transfer(cpi_ctx, amount)?;
+
+    self.dsp_state.balance = self.dsp_state.balance
+      .checked_add(amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    self.exchange_vault_state.total_balance = self.exchange_vault_state.total_balance
+      .checked_add(amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    emit!(DspFundsDeposited {
+      dsp: self.dsp.key(),
+      amount,
+      balance: self.dsp_state.balance,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+  }
+}
+
+// Move tokens from the DSP's prepaid balance back to its token account
+#[derive(Accounts)]
+pub struct WithdrawDspFunds<'info> {
+  #[account(mut)]
+  pub dsp: Signer<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"dsp", dsp.key().as_ref()],
+    bump = dsp_state.bump,
+  )]
+  pub dsp_state: Account<'info, DSP>,
+
+  #[account(
+    seeds = [b"adw3_config"],
+    bump,
+  )]
+  pub adw_config: Account<'info, ProtocolConfig>,
+
+  #[account(
+    mut,
+    seeds = [b"adw3_vault"],
+    bump = exchange_vault_state.bump,
+  )]
+  pub exchange_vault_state: Account<'info, ExchangeVault>,
+
+  #[account(
+    mut,
+    associated_token::mint = exchange_vault_state.token_mint,
+    associated_token::authority = exchange_vault_state,
+  )]
+  pub exchange_vault: Account<'info, TokenAccount>,
+
+  // DSP's token account
+  #[account(
+    mut,
+    associated_token::mint = exchange_vault_state.token_mint,
+    associated_token::authority = dsp,
+  )]
+  pub dsp_token_account: Account<'info, TokenAccount>,
+
+  pub token_program: Program<'info, Token>,
+  pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> WithdrawDspFunds<'info> {
+  pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+    require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
+    require!(amount > 0, AdW3Error::InvalidAmount);
+    // funds locked by in-flight bids can't be withdrawn
+    require!(amount <= self.dsp_state.available_balance(), AdW3Error::ExcessiveWithdrawalAmount);
+
+    let cpi_program = self.token_program.to_account_info();
+
+    let cpi_accounts = Transfer {
+      from: self.exchange_vault.to_account_info(),
+      to: self.dsp_token_account.to_account_info(),
+      authority: self.exchange_vault_state.to_account_info(),
+    };
+
+    // Need vault state seeds for signing
+    let vault_seeds = &[
+      b"adw3_vault".as_ref(),
+      &[self.exchange_vault_state.bump]
+    ];
+
+    let vault_signer = &[&vault_seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+
+    transfer(cpi_ctx, amount)?;
+
+    self.dsp_state.balance -= amount;
+
+    self.exchange_vault_state.total_balance = self.exchange_vault_state.total_balance
+      .checked_sub(amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    emit!(DspFundsWithdrawn {
+      dsp: self.dsp.key(),
+      amount,
+      balance: self.dsp_state.balance,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+  }
+}
+
+// Pay back a bid placed before prepaid balances existed. Those bids were transferred into
+// the vault and their responses can't be loaded as AdResponse anymore, so the tokens go
+// straight back to the DSP and the old response is closed
+#[derive(Accounts)]
+#[instruction(creative_id: [u8; 32])]
+pub struct RefundLegacyBid<'info> {
+  #[account(mut)]
+  pub dsp: Signer<'info>,
+
+  /// CHECK: response on the legacy layout, owner, discriminator and size are checked in refund
+  #[account(
+    mut,
+    seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &creative_id],
+    bump,
+  )]
+  pub legacy_response: UncheckedAccount<'info>,
+
+  #[account(
+    mut,
+    seeds = [b"adw3_vault"],
+    bump = exchange_vault_state.bump,
+  )]
+  pub exchange_vault_state: Account<'info, ExchangeVault>,
+
+  #[account(
+    mut,
+    associated_token::mint = exchange_vault_state.token_mint,
+    associated_token::authority = exchange_vault_state,
+  )]
+  pub exchange_vault: Account<'info, TokenAccount>,
+
+  // DSP's token account
+  #[account(
+    mut,
+    associated_token::mint = exchange_vault_state.token_mint,
+    associated_token::authority = dsp,
+  )]
+  pub dsp_token_account: Account<'info, TokenAccount>,
+
+  pub token_program: Program<'info, Token>,
+  pub system_program: Program<'info, System>,
+  pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> RefundLegacyBid<'info> {
+  pub fn refund(&mut self) -> Result<()> {
+    let response_info = self.legacy_response.to_account_info();
+
+    require_keys_eq!(*response_info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+
+    let legacy = {
+      let data = response_info.try_borrow_data()?;
+      require!(
+        data.len() == ANCHOR_DISCRIMINATOR + LegacyAdResponse::SPACE,
+        AdW3Error::BidNotRefundable
+      );
+      require!(
+        data[..ANCHOR_DISCRIMINATOR] == *AdResponse::DISCRIMINATOR,
+        ErrorCode::AccountDiscriminatorMismatch
+      );
+      LegacyAdResponse::deserialize(&mut &data[ANCHOR_DISCRIMINATOR..])?
+    };
+
+    require_keys_eq!(legacy.dsp, self.dsp.key(), AdW3Error::InvalidDSP);
+    // a winning bid already paid for its auction
+    require!(legacy.status != ResponseStatus::Win, AdW3Error::BidNotRefundable);
+
+    let cpi_program = self.token_program.to_account_info();
+
+    let cpi_accounts = Transfer {
+      from: self.exchange_vault.to_account_info(),
+      to: self.dsp_token_account.to_account_info(),
+      authority: self.exchange_vault_state.to_account_info(),
+    };
+
+    // Need vault state seeds for signing
+    let vault_seeds = &[
+      b"adw3_vault".as_ref(),
+      &[self.exchange_vault_state.bump]
+    ];
+
+    let vault_signer = &[&vault_seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+
+    transfer(cpi_ctx, legacy.bid_amount)?;
+
+    self.exchange_vault_state.total_balance = self.exchange_vault_state.total_balance
+      .checked_sub(legacy.bid_amount)
+      .ok_or(AdW3Error::Overflow)?;
+
+    // Close the old response, its rent goes back to the DSP
+    let dsp_info = self.dsp.to_account_info();
+    **dsp_info.try_borrow_mut_lamports()? = dsp_info.lamports()
+      .checked_add(response_info.lamports())
+      .ok_or(AdW3Error::Overflow)?;
+    **response_info.try_borrow_mut_lamports()? = 0;
+    response_info.assign(&System::id());
+    response_info.realloc(0, false)?;
+
+    emit!(BidRefunded {
+      request_id: legacy.request_id,
+      dsp: self.dsp.key(),
+      amount: legacy.bid_amount,
+      timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+  }
+}
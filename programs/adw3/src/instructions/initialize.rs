@@ -1,5 +1,5 @@
 use anchor_lang::prelude::*;
-use crate::state::{ProtocolConfig, ExchangeVault};
+use crate::state::{ProtocolConfig, ExchangeVault, RemainderPolicy};
 use anchor_spl::{
    associated_token::AssociatedToken,
    token::{
@@ -8,8 +8,7 @@ use anchor_spl::{
        TokenAccount
    }
 };
-use crate::constants::ANCHOR_DISCRIMINATOR;
-use crate::errors::AdW3Error;
+use crate::constants::{ANCHOR_DISCRIMINATOR, DEFAULT_MAX_REQUEST_TTL, DEFAULT_MIN_REQUEST_TTL};
 
 // start with the init of the program
 #[derive(Accounts)]
@@ -57,24 +56,23 @@ pub struct Initialize<'info> {
 impl<'info> Initialize<'info> {
    pub fn init(
      &mut self,
-     platform_fee_percentage: u8,
-     publisher_rev_share: u8,
+     platform_fee_bps: u16,
+     publisher_rev_share_bps: u16,
      bumps: InitializeBumps,
    ) -> Result<()> {
      
-     // Validate fee percentages
-    require!(platform_fee_percentage <= 100, AdW3Error::InvalidFeePercentage);
-    require!(publisher_rev_share <= 100, AdW3Error::InvalidRevenueShare);
-    require!(
-        platform_fee_percentage + publisher_rev_share <= 100,
-        AdW3Error::InvalidFeePercentage
-    );
+     // Validate fee basis points
+    ProtocolConfig::validate_fees(platform_fee_bps, publisher_rev_share_bps)?;
       
       // Initialize the protocol config account
       self.adw_config.set_inner(ProtocolConfig {
         authority: self.authority.key(),
-        platform_fee_percentage,
-        publisher_rev_share,
+        pending_authority: None,
+        platform_fee_bps,
+        publisher_rev_share_bps,
+        remainder_policy: RemainderPolicy::ProtocolReserve,
+        min_request_ttl: DEFAULT_MIN_REQUEST_TTL,
+        max_request_ttl: DEFAULT_MAX_REQUEST_TTL,
         is_paused: false,
         token_mint: self.token_mint.key(),
         bump: bumps.adw_config,
@@ -89,6 +87,7 @@ impl<'info> Initialize<'info> {
         token_mint: self.token_mint.key(),
         token_account: self.exchange_vault.key(),
         bump: bumps.exchange_vault_state,
+        reserve_balance: 0,
       });
       
       Ok(())
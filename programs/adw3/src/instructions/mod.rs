@@ -2,8 +2,14 @@ pub mod initialize;
 pub mod registry;
 pub mod auction;
 pub mod rollup;
+pub mod funds;
+pub mod admin;
+pub mod cleanup;
 
 pub use initialize::*;
 pub use registry::*;
 pub use auction::*;
 pub use rollup::*;
+pub use funds::*;
+pub use admin::*;
+pub use cleanup::*;
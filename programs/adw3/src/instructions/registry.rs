@@ -38,7 +38,7 @@ impl<'info> RegisterPublisher<'info> {
         require!(name.len() <= 50, AdW3Error::StringTooLong);
         require!(domain.len() <= 50, AdW3Error::StringTooLong);
 
-        require!(self.adw_config.is_paused == false, AdW3Error::ProtocolPaused);
+        require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
         
         // Use provided payment address or default to the publisher's address
         let payment = payment_address.unwrap_or(self.publisher.key());
@@ -94,14 +94,15 @@ impl <'info> RegisterDSP <'info> {
     require!(name.len() <= 50, AdW3Error::StringTooLong);
     require!(domain.len() <= 50, AdW3Error::StringTooLong);
 
-    require!(self.adw_config.is_paused == false, AdW3Error::ProtocolPaused);
+    require!(!self.adw_config.is_paused, AdW3Error::ProtocolPaused);
     // Initialize DSP state
     self.dsp_state.set_inner(DSP {
         authority: self.dsp.key(),
         name,
         domain,
         balance: 0,
-        // locked_amount: 0,
+        locked_amount: 0,
+        total_spent: 0,
         created_at: Clock::get()?.unix_timestamp,
         bump: bumps.dsp_state,
     });
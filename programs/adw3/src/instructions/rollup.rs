@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-  associated_token::AssociatedToken,
+  associated_token::{get_associated_token_address, AssociatedToken},
   token::{
       Token,
       TokenAccount,
@@ -11,9 +11,9 @@ use anchor_spl::{
 
 use ephemeral_rollups_sdk::anchor::{commit, delegate};
 
-use crate::constants::{AD_REQUEST_PDA_SEED, AD_RESPONSE_PDA_SEED, AUCTION_RECORD_PDA_SEED};
+use crate::constants::{AD_REQUEST_PDA_SEED, AD_RESPONSE_PDA_SEED, AUCTION_RECORD_PDA_SEED, BPS_DENOMINATOR, OPERATOR_PDA_SEED};
 use crate::errors::AdW3Error;
-use crate::state::{AdRequest, AdResponse, AuctionRecord, ExchangeVault, ProtocolConfig, Publisher, RequestStatus,  DSP};
+use crate::state::{AdRequest, AdResponse, AuctionRecord, AuctionState, ExchangeVault, Operator, ProtocolConfig, Publisher, RemainderPolicy, RequestStatus, DSP, AuctionsSettled};
 
 // Step 1: Delegate the ad request to the ER
 #[delegate]
@@ -37,7 +37,7 @@ pub struct DelegateAdRequest<'info> {
 // Delegate response accounts
 #[delegate]
 #[derive(Accounts)]
-#[instruction(creative_id: [u8; 32])]
+#[instruction(ad_request_id: [u8; 32], creative_id: [u8; 32])]
 pub struct DelegateAdResponse<'info> {
   #[account(mut)]
   pub authority: Signer<'info>,
@@ -47,7 +47,7 @@ pub struct DelegateAdResponse<'info> {
   #[account(
       mut,
       del,
-      seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &creative_id],
+      seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
       bump,
   )]
   pub ad_response: AccountInfo<'info>,
@@ -77,13 +77,23 @@ pub struct DelegateAuctionRecord<'info> {
 #[derive(Accounts)]
 #[instruction(ad_request_id: [u8; 32])]
 pub struct ProcessAuction<'info> {
-    #[account(mut)]
+    // protocol authority or an approved operator
+    #[account(
+        mut,
+        constraint = authority.key() == adw_config.authority || operator.is_some() @ AdW3Error::UnauthorizedAccess,
+    )]
     pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [OPERATOR_PDA_SEED, authority.key().as_ref()],
+        bump = operator.bump,
+    )]
+    pub operator: Option<Account<'info, Operator>>,
     
     #[account(
         mut,
-        seeds = [AD_REQUEST_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
-        bump,
+        seeds = [AD_REQUEST_PDA_SEED, ad_request.publisher.as_ref(), &ad_request_id],
+        bump = ad_request.bump,
         constraint = ad_request.status == RequestStatus::Open @ AdW3Error::RequestClosed,
         constraint = Clock::get()?.unix_timestamp < ad_request.expiration @ AdW3Error::RequestExpired,
     )]
@@ -91,8 +101,8 @@ pub struct ProcessAuction<'info> {
     
     // Keep read-only for minimal state updates in rollup
     #[account(
-        seeds = [b"publisher", publisher.key().as_ref()],
-        bump,
+        seeds = [b"publisher", ad_request.publisher.as_ref()],
+        bump = publisher.bump,
     )]
     pub publisher: Account<'info, Publisher>,
     
@@ -100,14 +110,16 @@ pub struct ProcessAuction<'info> {
     #[account(
         seeds = [b"adw3_config"],
         bump,
+        constraint = !adw_config.is_paused @ AdW3Error::ProtocolPaused,
     )]
     pub adw_config: Account<'info, ProtocolConfig>,
     
     // Auction record is already initialized during PlaceAsk
     #[account(
         mut,
-        seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
-        bump,
+        seeds = [AUCTION_RECORD_PDA_SEED, ad_request.publisher.as_ref(), &ad_request_id],
+        bump = auction_record.bump,
+        constraint = auction_record.auction_state == AuctionState::Delegated @ AdW3Error::AuctionNotDelegated,
     )]
     pub auction_record: Account<'info, AuctionRecord>,
     
@@ -119,22 +131,33 @@ pub struct ProcessAuction<'info> {
 #[derive(Accounts)]
 #[instruction(ad_request_id: [u8; 32])]
 pub struct ProcessAuctionResults<'info> {
-    #[account(mut)]
+    // protocol authority or an approved operator
+    #[account(
+        mut,
+        constraint = authority.key() == adw_config.authority || operator.is_some() @ AdW3Error::UnauthorizedAccess,
+    )]
     pub authority: Signer<'info>,
 
+    #[account(
+        seeds = [OPERATOR_PDA_SEED, authority.key().as_ref()],
+        bump = operator.bump,
+    )]
+    pub operator: Option<Account<'info, Operator>>,
+
     #[account(
         mut,
-        seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
-        bump,
-        constraint = !auction_record.is_settled @ AdW3Error::AuctionAlreadySettled,
+        seeds = [AUCTION_RECORD_PDA_SEED, auction_record.publisher.as_ref(), &ad_request_id],
+        bump = auction_record.bump,
+        constraint = matches!(auction_record.auction_state, AuctionState::Cleared | AuctionState::NoFill)
+            @ AdW3Error::AuctionNotCleared,
     )]
     pub auction_record: Account<'info, AuctionRecord>,
     
+    // record's publisher, the record stores the publisher's wallet
     #[account(
         mut,
-        seeds = [b"publisher", publisher.key().as_ref()],
-        bump,
-        constraint = publisher.key() == auction_record.publisher @ AdW3Error::InvalidPublisher,
+        seeds = [b"publisher", auction_record.publisher.as_ref()],
+        bump = publisher.bump,
     )]
     pub publisher: Account<'info, Publisher>,
     
@@ -175,7 +198,7 @@ pub struct UndelegateRequestAfterAuction<'info> {
 // For undelegating response after auction
 #[commit]
 #[derive(Accounts)]
-#[instruction(creative_id: [u8; 32])]
+#[instruction(ad_request_id: [u8; 32], creative_id: [u8; 32])]
 pub struct UndelegateResponseAfterAuction<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -185,7 +208,7 @@ pub struct UndelegateResponseAfterAuction<'info> {
     
     #[account(
         mut,
-        seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &creative_id],
+        seeds = [AD_RESPONSE_PDA_SEED, dsp.key().as_ref(), &ad_request_id, &creative_id],
         bump,
     )]
     pub ad_response: Account<'info, AdResponse>,
@@ -205,37 +228,63 @@ pub struct UndelegateAuctionRecordAfterAuction<'info> {
         mut,
         seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id], 
         bump,
+        // leaving the rollup before process_auction would strand the record in Delegated
+        constraint = matches!(
+            auction_record.auction_state,
+            AuctionState::Cleared | AuctionState::NoFill
+        ) @ AdW3Error::AuctionNotCleared,
     )]
     pub auction_record: Account<'info, AuctionRecord>,
 }
 
 // Final settlement instruction
 #[derive(Accounts)]
-#[instruction(ad_request_id: [u8; 32])]
 pub struct SettleAuction<'info> {
-    #[account(mut)]
+    // protocol authority or an approved operator
+    #[account(
+        mut,
+        constraint = authority.key() == adw_config.authority || operator.is_some() @ AdW3Error::UnauthorizedAccess,
+    )]
     pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [OPERATOR_PDA_SEED, authority.key().as_ref()],
+        bump = operator.bump,
+    )]
+    pub operator: Option<Account<'info, Operator>>,
     
     #[account(
         mut,
-        seeds = [AUCTION_RECORD_PDA_SEED, publisher.key().as_ref(), &ad_request_id],
-        bump,
-        constraint = !auction_record.is_settled @ AdW3Error::AuctionAlreadySettled,
+        seeds = [AUCTION_RECORD_PDA_SEED, auction_record.publisher.as_ref(), &auction_record.ad_request_id],
+        bump = auction_record.bump,
+        constraint = auction_record.auction_state != AuctionState::Settled @ AdW3Error::AuctionAlreadySettled,
+        constraint = auction_record.auction_state != AuctionState::NoFill @ AdW3Error::NothingToSettle,
+        constraint = auction_record.auction_state == AuctionState::Accounted @ AdW3Error::AuctionNotAccounted,
     )]
     pub auction_record: Account<'info, AuctionRecord>,
     
+    // record's publisher, the record stores the publisher's wallet
     #[account(
         mut,
-        seeds = [b"publisher", publisher.key().as_ref()],
-        bump,
-        constraint = publisher.key() == auction_record.publisher @ AdW3Error::InvalidPublisher,
+        seeds = [b"publisher", auction_record.publisher.as_ref()],
+        bump = publisher.bump,
     )]
     pub publisher: Account<'info, Publisher>,
 
     #[account(
-        mut,
-        seeds = [b"dsp", dsp.key().as_ref()],
+        seeds = [b"adw3_config"],
         bump,
+        constraint = !adw_config.is_paused @ AdW3Error::ProtocolPaused,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+
+    // Winning DSP, its locked bid is consumed at the clearing price. Settlement
+    // is only booked against the DSP recorded as the auction's winner
+    #[account(
+        mut,
+        seeds = [b"dsp", dsp.authority.as_ref()],
+        bump = dsp.bump,
+        constraint = Some(dsp.authority) == auction_record.winning_dsp @ AdW3Error::InvalidDSP,
     )]
     pub dsp: Account<'info, DSP>,
     
@@ -271,19 +320,37 @@ impl<'info> ProcessAuctionResults<'info> {
       &mut self,
       ad_request_id: [u8; 32],
      ) -> Result<()> {
-        // Ensure auction is not already settled
-        require!(!self.auction_record.is_settled, AdW3Error::AuctionAlreadySettled);
+        // Nothing to split when the auction went unfilled
+        if self.auction_record.auction_state == AuctionState::NoFill {
+            return Ok(());
+        }
+
         require!(self.auction_record.ad_request_id == ad_request_id, AdW3Error::InvalidAuctionId);
         
         
-        // Calculate fees based on clearing price
-        let clearing_price = self.auction_record.clearing_price;
-        let platform_fee = (clearing_price * self.adw_config.platform_fee_percentage as u64) / 100;
-        let publisher_payment = (clearing_price * self.adw_config.publisher_rev_share as u64) / 100;
+        // Calculate fees based on clearing price, in basis points. Both shares are
+        // rounded down together and the rounding dust of the split goes to the publisher
+        let clearing_price = self.auction_record.clearing_price as u128;
+        let bps = BPS_DENOMINATOR as u128;
+        let fee_bps = self.adw_config.platform_fee_bps as u128;
+        let rev_share_bps = self.adw_config.publisher_rev_share_bps as u128;
+
+        let platform_fee = (clearing_price * fee_bps / bps) as u64;
+        let distributed = (clearing_price * (fee_bps + rev_share_bps) / bps) as u64;
+        let publisher_payment = distributed
+            .checked_sub(platform_fee)
+            .ok_or(AdW3Error::Overflow)?;
         
+        // Whatever fee + rev share don't cover is handled per the configured remainder policy
+        let remainder = self.auction_record.clearing_price
+            .checked_sub(distributed)
+            .ok_or(AdW3Error::Overflow)?;
+
         // Update auction record with calculated fees
         self.auction_record.platform_fee = platform_fee;
         self.auction_record.publisher_payment = publisher_payment;
+        self.auction_record.remainder = remainder;
+        self.auction_record.remainder_policy = self.adw_config.remainder_policy;
         
         // Update publisher stats
         self.publisher.total_revenue = self.publisher.total_revenue
@@ -292,8 +359,10 @@ impl<'info> ProcessAuctionResults<'info> {
         
         // Update exchange vault
         self.exchange_vault_state.pending_settlements = self.exchange_vault_state.pending_settlements
-            .checked_add(publisher_payment + platform_fee)
+            .checked_add(self.auction_record.clearing_price)
             .ok_or(AdW3Error::Overflow)?;
+
+        self.auction_record.auction_state = AuctionState::Accounted;
   
         Ok(())
     }
@@ -301,20 +370,123 @@ impl<'info> ProcessAuctionResults<'info> {
 
 impl<'info> SettleAuction<'info> {
     pub fn settle(&mut self) -> Result<()> {
-        // // Update DSP stats - reduce locked amount
-        // self.dsp.locked_amount = self.dsp.locked_amount
-        //     .checked_sub(self.auction_record.clearing_price)
-        //     .ok_or(AdW3Error::Overflow)?;
+        // Need vault state seeds for signing
+        let vault_seeds = &[
+            b"adw3_vault".as_ref(),
+            &[self.exchange_vault_state.bump]
+        ];
 
-        // Begin transfer to publisher 
-        let cpi_program = self.token_program.to_account_info();
+        let vault_signer = &[&vault_seeds[..]];
 
+        // Begin transfer to publisher 
         let cpi_accounts = Transfer {
             from: self.exchange_vault.to_account_info(),
             to: self.publisher_token_account.to_account_info(),
             authority: self.exchange_vault_state.to_account_info(),
         };
 
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, vault_signer);
+
+        transfer(cpi_ctx, self.auction_record.publisher_payment)?;
+
+        book_settlement(&mut self.auction_record, &mut self.dsp, &mut self.exchange_vault_state)
+    }
+}
+
+// Settle many accounted auctions in one transaction. remaining_accounts holds
+// auction_count (auction_record, publisher, dsp) triples followed by the token
+// account of every distinct publisher payment_address, each paid with a single transfer
+#[derive(Accounts)]
+pub struct SettleAuctionsBatch<'info> {
+    // protocol authority or an approved operator
+    #[account(
+        mut,
+        constraint = authority.key() == adw_config.authority || operator.is_some() @ AdW3Error::UnauthorizedAccess,
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [OPERATOR_PDA_SEED, authority.key().as_ref()],
+        bump = operator.bump,
+    )]
+    pub operator: Option<Account<'info, Operator>>,
+
+    #[account(
+        seeds = [b"adw3_config"],
+        bump,
+        constraint = !adw_config.is_paused @ AdW3Error::ProtocolPaused,
+    )]
+    pub adw_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"adw3_vault"],
+        bump = exchange_vault_state.bump,
+    )]
+    pub exchange_vault_state: Account<'info, ExchangeVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = exchange_vault_state.token_mint,
+        associated_token::authority = exchange_vault_state,
+    )]
+    pub exchange_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> SettleAuctionsBatch<'info> {
+    pub fn settle_batch(
+        &mut self,
+        auction_count: u8,
+        batch_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let auction_count = auction_count as usize;
+        require!(
+            auction_count > 0 && batch_accounts.len() >= auction_count * 3,
+            AdW3Error::InvalidBatchAccounts
+        );
+        let (auctions, publisher_token_accounts) = batch_accounts.split_at(auction_count * 3);
+
+        // What each payment_address is owed, in order of first appearance
+        let mut payouts: Vec<(Pubkey, u64)> = Vec::new();
+
+        for auction in auctions.chunks(3) {
+            let mut auction_record = Account::<AuctionRecord>::try_from(&auction[0])?;
+
+            require!(
+                auction_record.auction_state != AuctionState::Settled,
+                AdW3Error::AuctionAlreadySettled
+            );
+            require!(
+                auction_record.auction_state != AuctionState::NoFill,
+                AdW3Error::NothingToSettle
+            );
+            require!(
+                auction_record.auction_state == AuctionState::Accounted,
+                AdW3Error::AuctionNotAccounted
+            );
+
+            let publisher = Account::<Publisher>::try_from(&auction[1])?;
+            require_keys_eq!(publisher.authority, auction_record.publisher, AdW3Error::InvalidPublisher);
+
+            let mut dsp = Account::<DSP>::try_from(&auction[2])?;
+            require!(Some(dsp.authority) == auction_record.winning_dsp, AdW3Error::InvalidDSP);
+
+            let payment = auction_record.publisher_payment;
+            match payouts.iter_mut().find(|(address, _)| *address == publisher.payment_address) {
+                Some((_, owed)) => *owed = owed.checked_add(payment).ok_or(AdW3Error::Overflow)?,
+                None => payouts.push((publisher.payment_address, payment)),
+            }
+
+            book_settlement(&mut auction_record, &mut dsp, &mut self.exchange_vault_state)?;
+
+            // Write back now, a DSP can win more than one auction in the batch
+            // and a repeated record must see itself as settled
+            auction_record.exit(&crate::ID)?;
+            dsp.exit(&crate::ID)?;
+        }
+
         // Need vault state seeds for signing
         let vault_seeds = &[
             b"adw3_vault".as_ref(),
@@ -323,22 +495,96 @@ impl<'info> SettleAuction<'info> {
 
         let vault_signer = &[&vault_seeds[..]];
 
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+        let mut total_paid: u64 = 0;
+
+        for (payment_address, amount) in payouts.iter() {
+            let publisher_token_account = get_associated_token_address(
+                payment_address,
+                &self.exchange_vault_state.token_mint,
+            );
+            let publisher_token_account = publisher_token_accounts
+                .iter()
+                .find(|info| info.key() == publisher_token_account)
+                .ok_or(AdW3Error::InvalidBatchAccounts)?;
+
+            let cpi_accounts = Transfer {
+                from: self.exchange_vault.to_account_info(),
+                to: publisher_token_account.clone(),
+                authority: self.exchange_vault_state.to_account_info(),
+            };
+
+            let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, vault_signer);
+
+            transfer(cpi_ctx, *amount)?;
+
+            total_paid = total_paid
+                .checked_add(*amount)
+                .ok_or(AdW3Error::Overflow)?;
+        }
+
+        emit!(AuctionsSettled {
+            auctions_settled: auction_count as u32,
+            publishers_paid: payouts.len() as u32,
+            total_paid,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        transfer(cpi_ctx, self.auction_record.publisher_payment)?;
-        
-        // Update exchange vault stats
-        self.exchange_vault_state.pending_settlements = self.exchange_vault_state.pending_settlements
-            .checked_sub(self.auction_record.publisher_payment + self.auction_record.platform_fee)
-            .ok_or(AdW3Error::Overflow)?;
-        
-        self.exchange_vault_state.fee_balance = self.exchange_vault_state.fee_balance
-            .checked_add(self.auction_record.platform_fee)
-            .ok_or(AdW3Error::Overflow)?;
-        
-        // Mark auction as settled
-        self.auction_record.is_settled = true;
-        
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Winner and vault bookkeeping once a publisher has been paid, shared by
+// settle_auction and settle_auctions_batch
+fn book_settlement(
+    auction_record: &mut AuctionRecord,
+    dsp: &mut DSP,
+    exchange_vault_state: &mut ExchangeVault,
+) -> Result<()> {
+    // A refunded remainder never leaves the winner's prepaid balance
+    let (charged, reserved) = match auction_record.remainder_policy {
+        RemainderPolicy::RefundDsp => (
+            auction_record.clearing_price - auction_record.remainder,
+            0,
+        ),
+        RemainderPolicy::ProtocolReserve => (
+            auction_record.clearing_price,
+            auction_record.remainder,
+        ),
+    };
+
+    // Update DSP stats - the winner pays the clearing price and the
+    // surplus of its bid is unlocked
+    dsp.locked_amount = dsp.locked_amount
+        .checked_sub(auction_record.bid_amount)
+        .ok_or(AdW3Error::Overflow)?;
+
+    dsp.balance = dsp.balance
+        .checked_sub(charged)
+        .ok_or(AdW3Error::Overflow)?;
+
+    dsp.total_spent = dsp.total_spent
+        .checked_add(charged)
+        .ok_or(AdW3Error::Overflow)?;
+    
+    // Update exchange vault stats
+    exchange_vault_state.total_balance = exchange_vault_state.total_balance
+        .checked_sub(auction_record.publisher_payment)
+        .ok_or(AdW3Error::Overflow)?;
+
+    exchange_vault_state.pending_settlements = exchange_vault_state.pending_settlements
+        .checked_sub(auction_record.clearing_price)
+        .ok_or(AdW3Error::Overflow)?;
+    
+    exchange_vault_state.fee_balance = exchange_vault_state.fee_balance
+        .checked_add(auction_record.platform_fee)
+        .ok_or(AdW3Error::Overflow)?;
+
+    exchange_vault_state.reserve_balance = exchange_vault_state.reserve_balance
+        .checked_add(reserved)
+        .ok_or(AdW3Error::Overflow)?;
+    
+    // Mark auction as settled
+    auction_record.auction_state = AuctionState::Settled;
+
+    Ok(())
+}
@@ -8,13 +8,16 @@ mod state;
 mod constants;
 mod errors;
 mod instructions;
+mod ranking;
 
 // Import from modules
 use instructions::*;
 use constants::{AD_REQUEST_PDA_SEED, AD_RESPONSE_PDA_SEED, AUCTION_RECORD_PDA_SEED};
+use ranking::{rank_bids, RankedBid};
+use errors::AdW3Error;
 use state::{
-    AdResponse, RequestStatus, ResponseStatus,
-    RequestDelegated, AuctionCompleted, 
+    AdResponse, AuctionRecord, AuctionState, AuctionType, RemainderPolicy, RequestStatus, ResponseStatus,
+    RequestDelegated, AuctionCompleted, AuctionNoFill, 
 };
 
 declare_id!("FdurepYmbwe1Wv2uqn91E45U5DS28Ai2uaFiwpgWaBri");
@@ -27,16 +30,91 @@ pub mod poynt_adw3 {
     // Initialize instruction handler
     pub fn initialize(
         ctx: Context<Initialize>,
-        platform_fee_percentage: u8,
-        publisher_rev_share: u8,
+        platform_fee_bps: u16,
+        publisher_rev_share_bps: u16,
     ) -> Result<()> {
        ctx.accounts.init(
-            platform_fee_percentage, 
-            publisher_rev_share,
+            platform_fee_bps, 
+            publisher_rev_share_bps,
             ctx.bumps
         )
     }
 
+    // Pause or unpause the protocol
+    pub fn set_paused(
+        ctx: Context<SetPaused>,
+        paused: bool,
+    ) -> Result<()> {
+        ctx.accounts.set_paused(paused)
+    }
+
+    // Update the protocol fee split and request lifetime bounds
+    pub fn update_protocol_config(
+        ctx: Context<UpdateProtocolConfig>,
+        platform_fee_bps: u16,
+        publisher_rev_share_bps: u16,
+        remainder_policy: RemainderPolicy,
+        min_request_ttl: i64,
+        max_request_ttl: i64,
+    ) -> Result<()> {
+        ctx.accounts.update(
+            platform_fee_bps,
+            publisher_rev_share_bps,
+            remainder_policy,
+            min_request_ttl,
+            max_request_ttl,
+        )
+    }
+
+    // Rewrite protocol accounts created on an older layout, legacy DSP accounts
+    // can be passed in remaining_accounts to be grown in the same call
+    pub fn migrate_protocol_config<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, MigrateProtocolConfig<'info>>,
+    ) -> Result<()>
+    where 'c: 'info
+    {
+        ctx.accounts.migrate(ctx.remaining_accounts)
+    }
+
+    // Nominate a new protocol authority
+    pub fn propose_authority(
+        ctx: Context<ProposeAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.propose(new_authority)
+    }
+
+    // Withdraw a pending authority nomination
+    pub fn cancel_authority_proposal(
+        ctx: Context<CancelAuthorityProposal>,
+    ) -> Result<()> {
+        ctx.accounts.cancel()
+    }
+
+    // Accept a pending nomination and take over the protocol authority
+    pub fn accept_authority(
+        ctx: Context<AcceptAuthority>,
+    ) -> Result<()> {
+        ctx.accounts.accept()
+    }
+
+    // Withdraw collected platform fees to a treasury token account
+    pub fn withdraw_platform_fees(
+        ctx: Context<WithdrawPlatformFees>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.withdraw(amount)
+    }
+
+    // Approve a key to crank auctions and settlement
+    pub fn add_operator(ctx: Context<AddOperator>, operator: Pubkey) -> Result<()> {
+        ctx.accounts.add(operator, ctx.bumps)
+    }
+
+    pub fn remove_operator(ctx: Context<RemoveOperator>, operator: Pubkey) -> Result<()> {
+        ctx.accounts.remove(operator)
+    }
+
     pub fn register_publisher(
         ctx: Context<RegisterPublisher>,
         name: String,
@@ -67,14 +145,28 @@ pub mod poynt_adw3 {
         ctx: Context<PlaceAsk>,
         ad_request_id: [u8; 32],
         ad_floor_price: u64,
+        ttl_seconds: i64,
+        auction_type: AuctionType,
     ) -> Result<()> {
         ctx.accounts.place_ask(
             ad_request_id,
             ad_floor_price,
+            ttl_seconds,
+            auction_type,
             ctx.bumps
         )
     }
 
+    // Withdraw an open ask, releasing its bids (passed as response/DSP pairs)
+    pub fn cancel_ad_ask<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, CancelAsk<'info>>,
+        ad_request_id: [u8; 32],
+    ) -> Result<()>
+    where 'c: 'info
+    {
+        ctx.accounts.cancel(ad_request_id, ctx.remaining_accounts)
+    }
+
     pub fn place_ad_bid(
         ctx: Context<PlaceBid>,
         ad_request_id: [u8; 32],
@@ -89,6 +181,83 @@ pub mod poynt_adw3 {
         )
     }
 
+    // Change the amount of a bid while its request is still open
+    pub fn update_ad_bid(
+        ctx: Context<UpdateBid>,
+        _ad_request_id: [u8; 32],
+        _creative_id: [u8; 32],
+        new_bid_amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.update_bid(new_bid_amount)
+    }
+
+    // Withdraw a bid from an open request, unlocking its escrow
+    pub fn retract_ad_bid(
+        ctx: Context<RetractBid>,
+        _ad_request_id: [u8; 32],
+        _creative_id: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.retract()
+    }
+
+    // Top up a DSP's prepaid bidding balance
+    pub fn deposit_dsp_funds(
+        ctx: Context<DepositDspFunds>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.deposit(amount)
+    }
+
+    // Withdraw from a DSP's prepaid bidding balance
+    pub fn withdraw_dsp_funds(
+        ctx: Context<WithdrawDspFunds>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.withdraw(amount)
+    }
+
+    // Return the vaulted tokens of a bid placed before prepaid balances existed
+    pub fn refund_legacy_bid(
+        ctx: Context<RefundLegacyBid>,
+        _creative_id: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.refund()
+    }
+
+    // Return a losing bid's escrow to the DSP's balance after the auction
+    pub fn refund_losing_bid(
+        ctx: Context<RefundLosingBid>,
+        _ad_request_id: [u8; 32],
+        _creative_id: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.refund()
+    }
+
+    // Expire a stale request, releasing its bids (passed as response/DSP pairs)
+    // and returning the request and record rent to the publisher
+    pub fn expire_ad_request<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ExpireAdRequest<'info>>,
+        ad_request_id: [u8; 32],
+    ) -> Result<()>
+    where 'c: 'info
+    {
+        ctx.accounts.expire(ad_request_id, ctx.remaining_accounts)
+    }
+
+    // Archive a settled auction, closing its request, record and winning response
+    pub fn close_settled_auction(ctx: Context<CloseSettledAuction>, ad_request_id: [u8; 32]) -> Result<()> {
+        ctx.accounts.close(ad_request_id)
+    }
+
+    // Close a response released by expiry or cancellation, the rent goes back to the DSP
+    pub fn close_ad_response(
+        _ctx: Context<CloseAdResponse>,
+        _ad_request_id: [u8; 32],
+        _creative_id: [u8; 32],
+    ) -> Result<()> {
+        Ok(())
+    }
+
     // ===== EPHEMERAL ROLLUPS FUNCTIONALITY =====
 
     // Delegate ad request to ER
@@ -115,11 +284,12 @@ pub mod poynt_adw3 {
     // Delegate ad response to ER
     pub fn delegate_ad_response(
         ctx: Context<DelegateAdResponse>,
+        ad_request_id: [u8; 32],
         creative_id: [u8; 32],
     ) -> Result<()> {
         ctx.accounts.delegate_ad_response(
             &ctx.accounts.authority,
-            &[AD_RESPONSE_PDA_SEED, ctx.accounts.dsp.key().as_ref(), &creative_id],
+            &[AD_RESPONSE_PDA_SEED, ctx.accounts.dsp.key().as_ref(), &ad_request_id, &creative_id],
             DelegateConfig::default()
         )?;
         
@@ -131,6 +301,18 @@ pub mod poynt_adw3 {
         ctx: Context<DelegateAuctionRecord>,
         ad_request_id: [u8; 32],
     ) -> Result<()> {
+        // Record the transition while the program still owns the account
+        {
+            let mut data = ctx.accounts.auction_record.try_borrow_mut_data()?;
+            let mut auction_record = AuctionRecord::try_deserialize(&mut &data[..])?;
+            require!(
+                auction_record.auction_state == AuctionState::Created,
+                AdW3Error::AuctionAlreadyDelegated
+            );
+            auction_record.auction_state = AuctionState::Delegated;
+            auction_record.try_serialize(&mut *data)?;
+        }
+
         ctx.accounts.delegate_auction_record(
             &ctx.accounts.authority,
             &[AUCTION_RECORD_PDA_SEED, ctx.accounts.publisher.key().as_ref(), &ad_request_id],
@@ -152,26 +334,22 @@ pub mod poynt_adw3 {
         ctx.accounts.ad_request.status = RequestStatus::AuctionInProgress;
         
         // Process all bid responses (from remaining_accounts)
-        let mut valid_bids: Vec<(u64, Pubkey, Pubkey)> = Vec::new(); // (bid_amount, dsp, response_key)
-        let mut response_map: std::collections::HashMap<Pubkey, &AccountInfo> = std::collections::HashMap::new();
+        let mut valid_bids: Vec<RankedBid> = Vec::new();
+        let mut supplied: std::collections::HashSet<Pubkey> = std::collections::HashSet::new();
     
-        // Process bids from remaining_accounts
+        // Process bids from remaining_accounts, every one must be a distinct response to this request
         for response_info in ctx.remaining_accounts.iter() {
-            // Skip if not a valid AdResponse account
-            if response_info.owner != &crate::ID {
-                continue;
-            }
-    
-            // Deserialize the response account
-            let response = match Account::<AdResponse>::try_from(response_info) {
-                Ok(response) => response,
-                Err(_) => continue, // Skip if deserialization fails
-            };
-    
-            // Skip if not for our particular request
-            if response.request_id != ad_request_id {
-                continue;
-            }
+            let response = Account::<AdResponse>::try_from(response_info)
+                .map_err(|_| error!(AdW3Error::InvalidBidAccounts))?;
+
+            require_keys_eq!(response.ad_request, ctx.accounts.ad_request.key(), AdW3Error::InvalidBidAccounts);
+            require!(response.status == ResponseStatus::Submitted, AdW3Error::InvalidBidAccounts);
+
+            let response_key = response_info.key();
+            require!(
+                supplied.insert(response_key),
+                AdW3Error::InvalidBidAccounts
+            );
     
             // Skip if bid is less than floor price
             if response.bid_amount < ctx.accounts.ad_request.floor_price {
@@ -179,86 +357,87 @@ pub mod poynt_adw3 {
             }
     
             // Add valid bid
-            let response_key = response_info.key();
-            valid_bids.push((response.bid_amount, response.dsp, response_key));
-            response_map.insert(response_key, response_info);
+            valid_bids.push(RankedBid {
+                amount: response.bid_amount,
+                created_at: response.created_at,
+                response: response_key,
+                dsp: response.dsp,
+            });
         }
+
+        // The cranker can't leave out a competing bid
+        require!(
+            supplied.len() as u32 == ctx.accounts.ad_request.bid_count,
+            AdW3Error::MissingBids
+        );
     
-        // If no valid bids found
-        if valid_bids.is_empty() {
-            // Mark request as completed with no winner
-            ctx.accounts.ad_request.status = RequestStatus::Completed;
-            
-            // Commit the updated ad request
-            commit_accounts(
-                &ctx.accounts.authority, 
-                vec![&ctx.accounts.ad_request.to_account_info()], 
-                &ctx.accounts.magic_context,
-                &ctx.accounts.magic_program,
-            )?;
-            return Ok(());
-        }
-        
-        // Sort bids in descending order (highest first)
-        valid_bids.sort_by(|a, b| b.0.cmp(&a.0));
-        
-        // Get winning bid (highest amount)
-        let winning_bid = &valid_bids[0].clone();
-        
-        // Determine clearing price (second-price auction logic)
-        let clearing_price = if valid_bids.len() > 1 {
-            // Use second highest bid price
-            valid_bids[1].0
+        // Rank bids best first, ties are broken on on-chain data (see ranking::rank_bids)
+        rank_bids(&mut valid_bids);
+
+        // Winning bid (highest amount), none means the request went unfilled
+        let winning_bid = valid_bids.first().copied();
+
+        if let Some(winning_bid) = winning_bid {
+            // Determine clearing price from the rule the publisher picked
+            let clearing_price = match ctx.accounts.ad_request.auction_type {
+                // Winner pays its own bid
+                AuctionType::FirstPrice => winning_bid.amount,
+                // Use second highest bid price, or the floor price if only one bid
+                AuctionType::SecondPrice => if valid_bids.len() > 1 {
+                    valid_bids[1].amount
+                } else {
+                    ctx.accounts.ad_request.floor_price
+                },
+            };
+
+            // Ensure clearing price is at least the floor price
+            let clearing_price = clearing_price.max(ctx.accounts.ad_request.floor_price);
+
+            // Update auction record with minimal info needed
+            ctx.accounts.auction_record.winning_dsp = Some(winning_bid.dsp);
+            ctx.accounts.auction_record.bid_amount = winning_bid.amount;
+            ctx.accounts.auction_record.clearing_price = clearing_price;
+            ctx.accounts.auction_record.auction_state = AuctionState::Cleared;
         } else {
-            // If only one bid, use floor price
-            ctx.accounts.ad_request.floor_price
-        };
-        
-        // Ensure clearing price is at least the floor price
-        let clearing_price = clearing_price.max(ctx.accounts.ad_request.floor_price);
-        
-        // Update auction record with minimal info needed
-        ctx.accounts.auction_record.winning_dsp = Some(winning_bid.1);
-        ctx.accounts.auction_record.bid_amount = winning_bid.0;
-        ctx.accounts.auction_record.clearing_price = clearing_price;
+            // No fill is final, nothing is owed and every bid can be refunded right away
+            ctx.accounts.auction_record.auction_state = AuctionState::NoFill;
+        }
         ctx.accounts.auction_record.timestamp = Clock::get()?.unix_timestamp;
 
-
         // Mark the request as completed
         ctx.accounts.ad_request.status = RequestStatus::Completed;
-        
+
         // Get winning key for comparison
-        let winning_key = winning_bid.2;
-
-        // Update and commit response accounts
-        for (_bid_amount, _dsp, response_key) in valid_bids {
-            if let Some(response_info) = response_map.get(&response_key) {
-                let is_winner = response_key == winning_key;
-                
-                // Get mutable copy to update
-                let mut response_data = Account::<AdResponse>::try_from(response_info)?;
-                
-                // Update status
-                response_data.status = if is_winner { 
-                    ResponseStatus::Win 
-                } else { 
-                    ResponseStatus::Loss 
-                };
-                
-                // Access the account info
-                let mut data = response_info.try_borrow_mut_data()?;
-                response_data.try_serialize(&mut *data)?;
-                
-                // Commit the updated account
-                commit_accounts(
-                    &ctx.accounts.authority,
-                    vec![*response_info],
-                    &ctx.accounts.magic_context,
-                    &ctx.accounts.magic_program,
-                )?;
-            }
+        let winning_key = winning_bid.map(|bid| bid.response);
+
+        // Update and commit every supplied response, anything but the winner is a loss
+        for response_info in ctx.remaining_accounts.iter() {
+            let is_winner = Some(response_info.key()) == winning_key;
+
+            // Get mutable copy to update
+            let mut response_data = Account::<AdResponse>::try_from(response_info)?;
+
+            // Update status
+            response_data.status = if is_winner {
+                ResponseStatus::Win
+            } else {
+                ResponseStatus::Loss
+            };
+
+            // Access the account info
+            let mut data = response_info.try_borrow_mut_data()?;
+            response_data.try_serialize(&mut *data)?;
+            drop(data);
+
+            // Commit the updated account
+            commit_accounts(
+                &ctx.accounts.authority,
+                vec![response_info],
+                &ctx.accounts.magic_context,
+                &ctx.accounts.magic_program,
+            )?;
         }
-        
+
         // Commit the updated ad request and auction record
         commit_accounts(
             &ctx.accounts.authority,
@@ -269,16 +448,24 @@ pub mod poynt_adw3 {
             &ctx.accounts.magic_context,
             &ctx.accounts.magic_program,
         )?;
-        
-        // Emit auction completion event
-        emit!(AuctionCompleted {
-            request_id: ad_request_id,
-            publisher: ctx.accounts.publisher.key(),
-            winning_dsp: winning_bid.1,
-            clearing_price,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
-        
+
+        match winning_bid {
+            // Emit auction completion event
+            Some(winning_bid) => emit!(AuctionCompleted {
+                request_id: ad_request_id,
+                publisher: ctx.accounts.publisher.key(),
+                winning_dsp: winning_bid.dsp,
+                clearing_price: ctx.accounts.auction_record.clearing_price,
+                timestamp: ctx.accounts.auction_record.timestamp,
+            }),
+            None => emit!(AuctionNoFill {
+                request_id: ad_request_id,
+                publisher: ctx.accounts.publisher.key(),
+                bids_supplied: supplied.len() as u32,
+                timestamp: ctx.accounts.auction_record.timestamp,
+            }),
+        }
+
         Ok(())
     }
 
@@ -310,6 +497,7 @@ pub mod poynt_adw3 {
     // Undelegate response account after auction
     pub fn undelegate_response_after_auction(
         ctx: Context<UndelegateResponseAfterAuction>,
+        _ad_request_id: [u8; 32],
         _creative_id: [u8; 32],
     ) -> Result<()> {
         // Undelegate the ad response account
@@ -345,4 +533,14 @@ pub mod poynt_adw3 {
     ) -> Result<()> {
         ctx.accounts.settle()
     }
+
+    // Settle several auctions at once, see SettleAuctionsBatch for the account layout
+    pub fn settle_auctions_batch<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, SettleAuctionsBatch<'info>>,
+        auction_count: u8,
+    ) -> Result<()>
+    where 'c: 'info
+    {
+        ctx.accounts.settle_batch(auction_count, ctx.remaining_accounts)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+
+// A bid as seen by process_auction, with the on-chain data used to order it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RankedBid {
+    pub amount: u64,
+    pub created_at: i64,
+    pub response: Pubkey,
+    pub dsp: Pubkey,
+}
+
+// Order bids best first: highest amount, then earliest AdResponse.created_at,
+// then lowest response pubkey. Every key comes from account data, so the
+// winner doesn't depend on the order the caller passed the responses in
+pub fn rank_bids(bids: &mut [RankedBid]) {
+    bids.sort_by(|a, b| {
+        b.amount
+            .cmp(&a.amount)
+            .then(a.created_at.cmp(&b.created_at))
+            .then(a.response.cmp(&b.response))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bid(amount: u64, created_at: i64, response: u8) -> RankedBid {
+        RankedBid {
+            amount,
+            created_at,
+            response: Pubkey::new_from_array([response; 32]),
+            dsp: Pubkey::new_from_array([response.wrapping_add(100); 32]),
+        }
+    }
+
+    fn permutations(bids: &[RankedBid]) -> Vec<Vec<RankedBid>> {
+        if bids.len() <= 1 {
+            return vec![bids.to_vec()];
+        }
+        let mut all = Vec::new();
+        for i in 0..bids.len() {
+            let mut rest = bids.to_vec();
+            let first = rest.remove(i);
+            for mut tail in permutations(&rest) {
+                tail.insert(0, first);
+                all.push(tail);
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn ranking_is_permutation_invariant() {
+        let bids = [
+            bid(500, 20, 1),
+            bid(500, 10, 7),
+            bid(500, 10, 3),
+            bid(900, 30, 9),
+            bid(100, 5, 2),
+        ];
+
+        let mut expected = bids.to_vec();
+        rank_bids(&mut expected);
+
+        for mut permutation in permutations(&bids) {
+            rank_bids(&mut permutation);
+            assert_eq!(permutation, expected);
+        }
+    }
+
+    #[test]
+    fn ties_break_on_created_at_then_pubkey() {
+        let mut bids = vec![bid(500, 20, 1), bid(500, 10, 7), bid(500, 10, 3), bid(900, 30, 9)];
+        rank_bids(&mut bids);
+
+        let order: Vec<u8> = bids.iter().map(|bid| bid.response.to_bytes()[0]).collect();
+        assert_eq!(order, vec![9, 3, 7, 1]);
+    }
+}
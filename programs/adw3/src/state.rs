@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use crate::constants::{BPS_DENOMINATOR, MIN_PUBLISHER_REV_SHARE_BPS};
+use crate::errors::AdW3Error;
 
 //this is the accounts struct/state for AD-W3
 //. publisher, dsp, exchange vault, auctionrecord, rollupinstance, protocolconfig
@@ -20,6 +22,7 @@ pub struct Publisher {
 
 #[account]
 #[derive(InitSpace)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct DSP {
     pub authority: Pubkey,
     #[max_len(50)] 
@@ -27,10 +30,17 @@ pub struct DSP {
     #[max_len(50)] 
     pub domain: String,
     pub balance: u64,
-    // pub locked_amount: u64,
     pub created_at: i64,
     pub bump: u8,
-    // we need to ad DSP's total ad spent
+    pub locked_amount: u64, // Portion of balance reserved by in-flight bids
+    pub total_spent: u64,   // Lifetime amount charged for won auctions, appended last so existing DSPs only need a realloc
+}
+
+impl DSP {
+    // Balance not reserved by in-flight bids
+    pub fn available_balance(&self) -> u64 {
+        self.balance.saturating_sub(self.locked_amount)
+    }
 }
 
 #[account]
@@ -43,9 +53,11 @@ pub struct ExchangeVault {
     pub token_mint: Pubkey,       // USDC token mint
     pub token_account: Pubkey,    // Token account holding USDC
     pub bump: u8,
+    pub reserve_balance: u64,     // Clearing price remainders kept as protocol reserve, appended last so existing vaults only need a realloc
 }
 
-// Account of each successful auction
+// Account of each successful auction. Fields added since the first release are appended after
+// bump, records still open on the old layout have to be settled before the program is upgraded
 #[account]
 #[derive(InitSpace)]
 pub struct AuctionRecord {
@@ -59,7 +71,34 @@ pub struct AuctionRecord {
     pub publisher_payment: u64,
     pub platform_fee: u64,
     pub timestamp: i64,
-    pub is_settled: bool,
+    pub auction_state: AuctionState,        // Lifecycle step, gates every auction instruction
+    pub bump: u8,
+    pub remainder: u64,                     // Part of the clearing price not covered by fee + rev share
+    pub remainder_policy: RemainderPolicy,  // Where the remainder goes, fixed when results are processed
+    pub auction_type: AuctionType,          // Clearing rule applied by process_auction
+}
+
+// Lifecycle of an AuctionRecord, each instruction only accepts the step before it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum AuctionState {
+    Created,    // place_ad_ask
+    Delegated,  // delegate_auction_record
+    Cleared,    // process_auction picked a winner in the rollup
+    Accounted,  // process_auction_results split the clearing price
+    Settled,    // settle_auction paid the publisher
+    NoFill,     // process_auction found no valid bid, final
+}
+
+impl Space for AuctionState{
+  const INIT_SPACE: usize = 1;
+}
+
+// Key approved by the protocol authority to crank auctions and settlement
+#[account]
+#[derive(InitSpace)]
+pub struct Operator {
+    pub operator: Pubkey,
+    pub added_at: i64,
     pub bump: u8,
 }
 
@@ -68,6 +107,8 @@ pub enum RequestStatus {
     Open,
     AuctionInProgress,
     Completed,
+    Expired,
+    Cancelled,
 }
 
 impl Space for RequestStatus{
@@ -75,18 +116,44 @@ impl Space for RequestStatus{
 }
 
 
+// What happens to the part of the clearing price left after the platform fee and publisher share
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum RemainderPolicy {
+    RefundDsp,        // Stays in the winning DSP's prepaid balance
+    ProtocolReserve,  // Booked to ExchangeVault::reserve_balance
+}
+
+impl Space for RemainderPolicy{
+  const INIT_SPACE: usize = 1;
+}
+
+// How the clearing price is derived from the ranked bids
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum AuctionType {
+    FirstPrice,   // Winner pays its own bid
+    SecondPrice,  // Winner pays the runner-up bid, or the floor price when bidding alone
+}
+
+impl Space for AuctionType{
+  const INIT_SPACE: usize = 1;
+}
+
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
 pub enum ResponseStatus {
     Submitted,
     AuctionInProgress,
     Win,
     Loss,
+    Refunded, // Escrow released without an auction, the account can be closed by the DSP
 }
 
 impl Space for ResponseStatus{
   const INIT_SPACE: usize = 1;
 }
 
+// Fields added since the first release are appended after bump. bid_count can't be rebuilt for
+// requests opened on the old layout, so those have to expire or complete before the upgrade
 #[account]
 #[derive(InitSpace)]
 pub struct AdRequest {
@@ -96,6 +163,8 @@ pub struct AdRequest {
     pub expiration: i64,
     pub status: RequestStatus, // Current status
     pub bump: u8,                  
+    pub auction_type: AuctionType, // Clearing rule chosen by the publisher
+    pub bid_count: u32, // Outstanding bids, all of them must be supplied to process_auction
 }
 
 #[account]
@@ -108,6 +177,7 @@ pub struct AdResponse {
     pub created_at: i64,           // When bid was submitted
     pub status: ResponseStatus,    // Current status
     pub bump: u8,
+    pub ad_request: Pubkey,        // AdRequest account bid on, request ids are only unique per publisher
 }
 
 // #[account]
@@ -129,6 +199,44 @@ pub struct AdResponse {
 #[account]
 #[derive(InitSpace)]
 pub struct ProtocolConfig {
+    pub authority: Pubkey,
+    pub pending_authority: Option<Pubkey>, // Proposed authority awaiting acceptance
+    pub platform_fee_bps: u16,        // Platform cut of the clearing price, in basis points
+    pub publisher_rev_share_bps: u16, // Publisher cut of the clearing price, in basis points
+    pub remainder_policy: RemainderPolicy,
+    pub min_request_ttl: i64,         // Shortest ad request lifetime, in seconds
+    pub max_request_ttl: i64,         // Longest ad request lifetime, in seconds
+    pub is_paused: bool,
+    pub token_mint: Pubkey,
+    pub bump: u8,
+}
+
+impl ProtocolConfig {
+    // Fee split checks shared by initialize and update_protocol_config
+    pub fn validate_fees(platform_fee_bps: u16, publisher_rev_share_bps: u16) -> Result<()> {
+        require!(platform_fee_bps <= BPS_DENOMINATOR, AdW3Error::InvalidFeePercentage);
+        require!(publisher_rev_share_bps <= BPS_DENOMINATOR, AdW3Error::InvalidRevenueShare);
+        require!(publisher_rev_share_bps >= MIN_PUBLISHER_REV_SHARE_BPS, AdW3Error::RevShareTooLow);
+        require!(
+            platform_fee_bps as u32 + publisher_rev_share_bps as u32 <= BPS_DENOMINATOR as u32,
+            AdW3Error::InvalidFeePercentage
+        );
+        Ok(())
+    }
+
+    // Request lifetime bounds checks shared by initialize and update_protocol_config
+    pub fn validate_ttl_bounds(min_request_ttl: i64, max_request_ttl: i64) -> Result<()> {
+        require!(
+            min_request_ttl > 0 && min_request_ttl <= max_request_ttl,
+            AdW3Error::InvalidTtlBounds
+        );
+        Ok(())
+    }
+}
+
+// ProtocolConfig as it was stored before fees moved to basis points, only read by migrate_protocol_config
+#[derive(AnchorDeserialize)]
+pub struct LegacyProtocolConfig {
     pub authority: Pubkey,
     pub platform_fee_percentage: u8,
     pub publisher_rev_share: u8,
@@ -137,6 +245,28 @@ pub struct ProtocolConfig {
     pub bump: u8,
 }
 
+impl LegacyProtocolConfig {
+    pub const SPACE: usize = 32 + 1 + 1 + 1 + 32 + 1;
+}
+
+// AdResponse as it was stored when place_ad_bid moved the bid into the vault, seeded by
+// [ad_response, dsp, creative_id]. Only read by refund_legacy_bid
+#[derive(AnchorDeserialize)]
+#[allow(dead_code)] // kept whole so it matches the stored layout
+pub struct LegacyAdResponse {
+    pub dsp: Pubkey,
+    pub request_id: [u8; 32],
+    pub bid_amount: u64,
+    pub creative_id: [u8; 32],
+    pub created_at: i64,
+    pub status: ResponseStatus,
+    pub bump: u8,
+}
+
+impl LegacyAdResponse {
+    pub const SPACE: usize = 32 + 32 + 8 + 32 + 8 + 1 + 1;
+}
+
 
 
 //EVENTS 
@@ -155,4 +285,148 @@ pub struct AuctionCompleted {
     pub winning_dsp: Pubkey,
     pub clearing_price: u64,
     pub timestamp: i64,
-}
\ No newline at end of file
+}
+
+#[event]
+pub struct BidRefunded {
+    pub request_id: [u8; 32],
+    pub dsp: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DspFundsDeposited {
+    pub dsp: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DspFundsWithdrawn {
+    pub dsp: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolPauseUpdated {
+    pub authority: Pubkey,
+    pub is_paused: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub authority: Pubkey,
+    pub old_platform_fee_bps: u16,
+    pub new_platform_fee_bps: u16,
+    pub old_publisher_rev_share_bps: u16,
+    pub new_publisher_rev_share_bps: u16,
+    pub old_remainder_policy: RemainderPolicy,
+    pub new_remainder_policy: RemainderPolicy,
+    pub old_min_request_ttl: i64,
+    pub new_min_request_ttl: i64,
+    pub old_max_request_ttl: i64,
+    pub new_max_request_ttl: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityProposalCancelled {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PlatformFeesWithdrawn {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub fee_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdRequestExpired {
+    pub request_id: [u8; 32],
+    pub publisher: Pubkey,
+    pub bids_refunded: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionArchived {
+    pub request_id: [u8; 32],
+    pub publisher: Pubkey,
+    pub winning_dsp: Option<Pubkey>,
+    pub bid_amount: u64,
+    pub clearing_price: u64,
+    pub publisher_payment: u64,
+    pub platform_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdRequestCancelled {
+    pub request_id: [u8; 32],
+    pub publisher: Pubkey,
+    pub bids_refunded: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidUpdated {
+    pub request_id: [u8; 32],
+    pub dsp: Pubkey,
+    pub old_amount: u64,
+    pub new_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OperatorAdded {
+    pub authority: Pubkey,
+    pub operator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OperatorRemoved {
+    pub authority: Pubkey,
+    pub operator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionNoFill {
+    pub request_id: [u8; 32],
+    pub publisher: Pubkey,
+    pub bids_supplied: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionsSettled {
+    pub auctions_settled: u32,
+    pub publishers_paid: u32,
+    pub total_paid: u64,
+    pub timestamp: i64,
+}
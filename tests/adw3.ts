@@ -69,10 +69,17 @@ describe("poynt-adw3", () => {
   const dsp1Owner = loadKeypair("test-keypairs/dsp1.json");
   const dsp2Owner = loadKeypair("test-keypairs/dsp2.json");
   const authority = provider.wallet;
+  // Approved operator that cranks the main auction's results and settlement
+  const crankOperator = anchor.web3.Keypair.generate();
 
   // Test parameters
-  const platformFeePercentage = 20; // 20%
-  const publisherRevShare = 80; // 80%
+  const platformFeeBps = 2000; // 20%
+  const publisherRevShareBps = 8000; // 80%
+  const dspDepositAmount = new BN(5000000); // 5 tokens prepaid per DSP
+  const minRequestTtl = new BN(1); // 1 second
+  const maxRequestTtl = new BN(60 * 60 * 24 * 30); // 30 days
+  const requestTtl = new BN(60 * 60 * 12); // 12 hours
+  const auctionType = { secondPrice: {} };
   // Names and domains
   const publisherName = "Poynt Publisher";
   const publisherDomain = "www.poyntad.com";
@@ -91,10 +98,12 @@ describe("poynt-adw3", () => {
   let publisherTokenAccount: PublicKey;
   let dsp1TokenAccount: PublicKey;
   let dsp2TokenAccount: PublicKey;
+  let treasuryTokenAccount: PublicKey;
   let adRequest: PublicKey;
   let adResponse1: PublicKey;
   let adResponse2: PublicKey;
   let auctionRecord: PublicKey;
+  let crankOperatorState: PublicKey;
 
   let dsp1TokenBalanceBefore, dsp2TokenBalanceBefore;
   let dsp1TokenBalanceAfter, dsp2TokenBalanceAfter;
@@ -113,6 +122,81 @@ describe("poynt-adw3", () => {
     }
   }
 
+  // Create a standalone ad request, used by the request lifecycle tests
+  async function placeStandaloneAsk(
+    ttl: BN,
+    type: { firstPrice: {} } | { secondPrice: {} } = { firstPrice: {} },
+    floorPrice: BN = new BN(1000000)
+  ) {
+    const id = Array.from(anchor.web3.Keypair.generate().publicKey.toBytes());
+    const [request] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ad_request"),
+        publisherOwner.publicKey.toBuffer(),
+        Buffer.from(id),
+      ],
+      program.programId
+    );
+    const [record] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("auction_record"),
+        publisherOwner.publicKey.toBuffer(),
+        Buffer.from(id),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .placeAdAsk(id, floorPrice, ttl, type)
+      .accountsPartial({
+        publisher: publisherOwner.publicKey,
+        publisherState,
+        auctionRecord: record,
+        adwConfig,
+        adRequest: request,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([publisherOwner])
+      .rpc();
+
+    return { id, request, record };
+  }
+
+  // Place a bid from dsp1 against a standalone request
+  async function placeStandaloneBid(
+    id: number[],
+    request: PublicKey,
+    amount: BN
+  ) {
+    const creativeId = Array.from(
+      anchor.web3.Keypair.generate().publicKey.toBytes()
+    );
+    const [response] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ad_response"),
+        dsp1Owner.publicKey.toBuffer(),
+        Buffer.from(id),
+        Buffer.from(creativeId),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .placeAdBid(id, amount, creativeId)
+      .accountsPartial({
+        dsp: dsp1Owner.publicKey,
+        dspState: dsp1,
+        adDspResponse: response,
+        adRequest: request,
+        adwConfig,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([dsp1Owner])
+      .rpc();
+
+    return { creativeId, response };
+  }
+
   const tokenMint = new PublicKey(
     "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU"
   );
@@ -237,7 +321,8 @@ describe("poynt-adw3", () => {
   async function delegateAdResponse(
     creativeId: number[],
     adResp: PublicKey,
-    dspPubkey: PublicKey
+    dspPubkey: PublicKey,
+    adReqId: number[] = adRequestId
   ): Promise<boolean> {
     if (!ephemeralRollupConnected) {
       console.warn(
@@ -253,7 +338,7 @@ describe("poynt-adw3", () => {
 
       // Create and send delegation transaction directly
       let tx = await program.methods
-        .delegateAdResponse(creativeId)
+        .delegateAdResponse(adReqId, creativeId)
         .accountsPartial({
           authority: authority.publicKey,
           dsp: dspPubkey,
@@ -280,7 +365,10 @@ describe("poynt-adw3", () => {
     }
   }
 
-  async function delegateAuctionRecord(adReqId: number[]): Promise<boolean> {
+  async function delegateAuctionRecord(
+    adReqId: number[],
+    record: PublicKey = auctionRecord
+  ): Promise<boolean> {
     if (!ephemeralRollupConnected) {
       console.warn(
         "⚠️ Cannot delegate ad response: Ephemeral Rollup not connected"
@@ -299,7 +387,7 @@ describe("poynt-adw3", () => {
         .accountsPartial({
           authority: authority.publicKey,
           publisher: publisherOwner.publicKey,
-          auctionRecord,
+          auctionRecord: record,
         })
         .transaction();
 
@@ -322,6 +410,148 @@ describe("poynt-adw3", () => {
     }
   }
 
+  // Sign with the local wallet and send through the ephemeral rollup
+  async function sendToEphemeralRollup(
+    tx: anchor.web3.Transaction
+  ): Promise<string> {
+    tx.feePayer = wallet.publicKey;
+    tx.recentBlockhash = (
+      await providerEphemeralRollup.connection.getLatestBlockhash()
+    ).blockhash;
+    tx = await wallet.signTransaction(tx);
+
+    const txHash = await providerEphemeralRollup.connection.sendRawTransaction(
+      tx.serialize(),
+      { skipPreflight: true }
+    );
+    const { value } =
+      await providerEphemeralRollup.connection.confirmTransaction(
+        txHash,
+        "confirmed"
+      );
+    if (value.err) {
+      throw new Error(`(ER) ${txHash} failed: ${JSON.stringify(value.err)}`);
+    }
+    return txHash;
+  }
+
+  // Top up dsp1's prepaid balance for the bids of a standalone auction
+  async function depositDsp1(amount: BN) {
+    await program.methods
+      .depositDspFunds(amount)
+      .accountsPartial({
+        dsp: dsp1Owner.publicKey,
+        dspState: dsp1,
+        adwConfig,
+        exchangeVaultState,
+        exchangeVault,
+        dspTokenAccount: dsp1TokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([dsp1Owner])
+      .rpc();
+  }
+
+  // Run a standalone request with dsp1 bids of the given amounts through the rollup
+  // auction, leaving its record accounted on the base layer and ready to settle
+  async function runAuctionToAccounted(
+    amounts: BN[],
+    type: { firstPrice: {} } | { secondPrice: {} } = { firstPrice: {} }
+  ) {
+    const { id, request, record } = await placeStandaloneAsk(requestTtl, type);
+    const bids = [];
+    for (const amount of amounts) {
+      bids.push(await placeStandaloneBid(id, request, amount));
+    }
+
+    assert.isTrue(await delegateAdRequest(request, id));
+    assert.isTrue(await delegateAuctionRecord(id, record));
+    for (const { creativeId, response } of bids) {
+      assert.isTrue(
+        await delegateAdResponse(creativeId, response, dsp1Owner.publicKey, id)
+      );
+    }
+
+    await sendToEphemeralRollup(
+      await program.methods
+        .processAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          adRequest: request,
+          publisher: publisherState,
+          adwConfig,
+          auctionRecord: record,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .remainingAccounts(
+          bids.map(({ response }) => ({
+            pubkey: response,
+            isSigner: false,
+            isWritable: true,
+          }))
+        )
+        .transaction()
+    );
+
+    await sendToEphemeralRollup(
+      await program.methods
+        .undelegateRequestAfterAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          adRequest: request,
+          publisher: publisherOwner.publicKey,
+        })
+        .transaction()
+    );
+    for (const { creativeId, response } of bids) {
+      await sendToEphemeralRollup(
+        await program.methods
+          .undelegateResponseAfterAuction(id, creativeId)
+          .accountsPartial({
+            authority: authority.publicKey,
+            adResponse: response,
+            dsp: dsp1Owner.publicKey,
+          })
+          .transaction()
+      );
+    }
+    await sendToEphemeralRollup(
+      await program.methods
+        .undelegateAuctionRecordAfterAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          publisher: publisherOwner.publicKey,
+          auctionRecord: record,
+        })
+        .transaction()
+    );
+
+    // Wait for the record and responses to be handed back to the program on the base layer
+    for (const account of [record, ...bids.map(({ response }) => response)]) {
+      for (let attempt = 0; attempt < 30; attempt++) {
+        const info = await provider.connection.getAccountInfo(account);
+        if (info?.owner.equals(program.programId)) break;
+        await new Promise((resolve) => setTimeout(resolve, 1000));
+      }
+    }
+
+    await program.methods
+      .processAuctionResults(id)
+      .accountsPartial({
+        authority: authority.publicKey,
+        operator: null,
+        auctionRecord: record,
+        publisher: publisherState,
+        adwConfig,
+        exchangeVaultState,
+      })
+      .rpc();
+
+    return { id, request, record, bids };
+  }
+
   // Function to create and check token accounts for users
   async function setupTokenAccounts() {
     console.log("Setting up token accounts...");
@@ -368,6 +598,9 @@ describe("poynt-adw3", () => {
     dsp2TokenAccount = await getOrCreateAssociatedTokenAccount(
       dsp2Owner.publicKey
     );
+    treasuryTokenAccount = await getOrCreateAssociatedTokenAccount(
+      authority.publicKey
+    );
 
     // Calculate the exchange vault as an ASSOCIATED token account
     exchangeVault = await getAssociatedTokenAddress(
@@ -435,6 +668,7 @@ describe("poynt-adw3", () => {
       [
         Buffer.from("ad_response"),
         dsp1Owner.publicKey.toBuffer(),
+        Buffer.from(adRequestId),
         Buffer.from(creative1Id),
       ],
       program.programId
@@ -444,6 +678,7 @@ describe("poynt-adw3", () => {
       [
         Buffer.from("ad_response"),
         dsp2Owner.publicKey.toBuffer(),
+        Buffer.from(adRequestId),
         Buffer.from(creative2Id),
       ],
       program.programId
@@ -471,12 +706,35 @@ describe("poynt-adw3", () => {
   it("Initializes the protocol", async function () {
     this.timeout(30000);
 
+    // Accounts created on an older layout need migrating first
+    const LEGACY_CONFIG_SIZE = 8 + 32 + 1 + 1 + 1 + 32 + 1;
+    const LEGACY_VAULT_SIZE = 8 + 32 + 8 + 8 + 8 + 32 + 32 + 1;
+    const existingConfig = await provider.connection.getAccountInfo(adwConfig);
+    const existingVault = await provider.connection.getAccountInfo(
+      exchangeVaultState
+    );
+    if (
+      existingConfig?.data.length === LEGACY_CONFIG_SIZE ||
+      existingVault?.data.length === LEGACY_VAULT_SIZE
+    ) {
+      const tx = await program.methods
+        .migrateProtocolConfig()
+        .accountsPartial({
+          authority: authority.publicKey,
+          adwConfig,
+          exchangeVaultState,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      console.log("Migrated legacy protocol accounts, txHash:", tx);
+    }
+
     const result = await initializeIfNeeded(
       "Protocol Config",
       async () => await program.account.protocolConfig.fetch(adwConfig),
       async () => {
         const tx = await program.methods
-          .initialize(platformFeePercentage, publisherRevShare)
+          .initialize(platformFeeBps, publisherRevShareBps)
           .accountsPartial({
             authority: authority.publicKey,
             tokenMint: tokenMint,
@@ -499,8 +757,8 @@ describe("poynt-adw3", () => {
     console.log("Expected authority:", authority.publicKey.toString());
 
     assert.equal(config.authority.toString(), authority.publicKey.toString());
-    assert.equal(config.platformFeePercentage, platformFeePercentage);
-    assert.equal(config.publisherRevShare, publisherRevShare);
+    assert.equal(config.platformFeeBps, platformFeeBps);
+    assert.equal(config.publisherRevShareBps, publisherRevShareBps);
 
     // Verify exchange vault state
     const vaultState = await program.account.exchangeVault.fetch(
@@ -609,150 +867,742 @@ describe("poynt-adw3", () => {
     console.log("DSPs registered successfully");
   });
 
-  // Test creating ad request
-  it("Creates an ad request", async function () {
+  it("Pauses and unpauses the protocol", async function () {
     this.timeout(30000);
 
-    const floorPrice = new BN(1000000); // 1 token with 6 decimals
+    // Only the config authority may toggle the pause flag
+    try {
+      await program.methods
+        .setPaused(true)
+        .accountsPartial({ authority: dsp1Owner.publicKey, adwConfig })
+        .signers([dsp1Owner])
+        .rpc();
+      assert.fail("Non-authority should not be able to pause");
+    } catch (e) {
+      assert.include(e.toString(), "UnauthorizedAccess");
+    }
+
+    await program.methods
+      .setPaused(true)
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
+    assert.isTrue(
+      (await program.account.protocolConfig.fetch(adwConfig)).isPaused
+    );
 
+    // Money movement is rejected while paused
     try {
-      // Create ad request
-      const tx = await program.methods
-        .placeAdAsk(adRequestId, floorPrice)
+      await program.methods
+        .depositDspFunds(dspDepositAmount)
         .accountsPartial({
-          publisher: publisherOwner.publicKey,
-          publisherState,
-          auctionRecord,
+          dsp: dsp1Owner.publicKey,
+          dspState: dsp1,
           adwConfig,
-          adRequest,
-          systemProgram: anchor.web3.SystemProgram.programId,
+          exchangeVaultState,
+          exchangeVault,
+          dspTokenAccount: dsp1TokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         })
-        .signers([publisherOwner])
+        .signers([dsp1Owner])
         .rpc();
+      assert.fail("Deposit should be rejected while paused");
+    } catch (e) {
+      assert.include(e.toString(), "ProtocolPaused");
+    }
 
-      console.log("Ad request created, txHash:", tx);
-
-      // Verify ad request
-      const adRequestAccount = await program.account.adRequest.fetch(adRequest);
+    // So is settlement, the pause check runs before any auction is looked at
+    try {
+      await program.methods
+        .settleAuctionsBatch(1)
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          adwConfig,
+          exchangeVaultState,
+          exchangeVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Settlement should be rejected while paused");
+    } catch (e) {
+      assert.include(e.toString(), "ProtocolPaused");
+    }
 
-      console.log("the passed ad request", adRequestAccount);
+    await program.methods
+      .setPaused(false)
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
+    assert.isFalse(
+      (await program.account.protocolConfig.fetch(adwConfig)).isPaused
+    );
 
-      assert.equal(
-        adRequestAccount.publisher.toString(),
-        publisherOwner.publicKey.toString()
-      );
-      assert.equal(
-        adRequestAccount.floorPrice.toString(),
-        floorPrice.toString()
-      );
+    console.log("Protocol pause toggled successfully");
+  });
 
-      // Verify expiration time is roughly 12 hours from now
-      const currentTime = Math.floor(Date.now() / 1000);
-      const expectedExpiration = currentTime + 60 * 60 * 12; // 12 hours
-      assert.approximately(
-        adRequestAccount.expiration.toNumber(),
-        expectedExpiration,
-        300 // Allow 5 min difference for processing time
-      );
+  it("Updates protocol fee parameters", async function () {
+    this.timeout(30000);
 
-      console.log("Ad request created successfully");
+    // Revenue share below the program minimum is rejected
+    try {
+      await program.methods
+        .updateProtocolConfig(
+          1000,
+          4000,
+          { protocolReserve: {} },
+          minRequestTtl,
+          maxRequestTtl
+        )
+        .accountsPartial({ authority: authority.publicKey, adwConfig })
+        .rpc();
+      assert.fail("Revenue share below minimum should be rejected");
     } catch (e) {
-      console.error("Failed to create ad request:", e);
-      throw e;
+      assert.include(e.toString(), "RevShareTooLow");
     }
+
+    // Fractional percentages are expressible in basis points (12.5% / 87.5%)
+    await program.methods
+      .updateProtocolConfig(
+        1250,
+        8750,
+        { refundDsp: {} },
+        new BN(5),
+        new BN(60 * 60 * 24 * 7)
+      )
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
+
+    let config = await program.account.protocolConfig.fetch(adwConfig);
+    assert.equal(config.platformFeeBps, 1250);
+    assert.equal(config.publisherRevShareBps, 8750);
+    assert.deepEqual(config.remainderPolicy, { refundDsp: {} });
+    assert.equal(config.minRequestTtl.toNumber(), 5);
+    assert.equal(config.maxRequestTtl.toNumber(), 60 * 60 * 24 * 7);
+
+    // Restore the parameters the rest of the suite expects
+    await program.methods
+      .updateProtocolConfig(
+        platformFeeBps,
+        publisherRevShareBps,
+        { protocolReserve: {} },
+        minRequestTtl,
+        maxRequestTtl
+      )
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
+
+    config = await program.account.protocolConfig.fetch(adwConfig);
+    assert.equal(config.platformFeeBps, platformFeeBps);
+    assert.equal(config.publisherRevShareBps, publisherRevShareBps);
+
+    console.log("Protocol config updated successfully");
   });
 
-  // Test delegating accounts to ephemeral rollup
-  it("Delegates ad request to ephemeral rollup", async function () {
+  it("Rotates the protocol authority in two steps", async function () {
     this.timeout(60000);
 
-    // Skip if ephemeral rollup not connected
-    if (!ephemeralRollupConnected) {
-      console.warn(
-        "⚠️ Skipping delegation test: Ephemeral Rollup not connected"
-      );
-      this.skip();
-    }
-
-    try {
-      // Delegate ad request
-      const start = Date.now();
-      const success = await delegateAdRequest(adRequest, adRequestId);
-      const duration = Date.now() - start;
+    // A proposal can be cancelled before it is accepted
+    await program.methods
+      .proposeAuthority(dsp2Owner.publicKey)
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
+    await program.methods
+      .cancelAuthorityProposal()
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
+    assert.isNull(
+      (await program.account.protocolConfig.fetch(adwConfig)).pendingAuthority
+    );
 
-      console.log(`${duration}ms (Base Layer) Delegate Ad Request completed`);
-      assert.isTrue(success, "Failed to delegate ad request");
+    // Hand the authority to DSP 1's key, only the nominee can accept
+    await program.methods
+      .proposeAuthority(dsp1Owner.publicKey)
+      .accountsPartial({ authority: authority.publicKey, adwConfig })
+      .rpc();
 
-      console.log("Ad request delegated to ephemeral rollup successfully");
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accountsPartial({
+          newAuthority: dsp2Owner.publicKey,
+          adwConfig,
+          exchangeVaultState,
+        })
+        .signers([dsp2Owner])
+        .rpc();
+      assert.fail("Only the nominee should be able to accept");
     } catch (e) {
-      console.error("Failed to delegate ad request:", e);
-      throw e;
-    }
-  });
-
-  it("Delegates auction Record to ephemeral rollup", async function () {
-    this.timeout(60000);
-
-    // Skip if ephemeral rollup not connected
-    if (!ephemeralRollupConnected) {
-      console.warn(
-        "⚠️ Skipping delegation test: Ephemeral Rollup not connected"
-      );
-      this.skip();
+      assert.include(e.toString(), "UnauthorizedAccess");
     }
 
-    try {
-      // Delegate ad request
-      const start = Date.now();
-      const success = await delegateAuctionRecord(adRequestId);
-      const duration = Date.now() - start;
+    await program.methods
+      .acceptAuthority()
+      .accountsPartial({
+        newAuthority: dsp1Owner.publicKey,
+        adwConfig,
+        exchangeVaultState,
+      })
+      .signers([dsp1Owner])
+      .rpc();
+
+    let config = await program.account.protocolConfig.fetch(adwConfig);
+    let vaultState = await program.account.exchangeVault.fetch(
+      exchangeVaultState
+    );
+    assert.equal(config.authority.toString(), dsp1Owner.publicKey.toString());
+    assert.equal(
+      vaultState.authority.toString(),
+      dsp1Owner.publicKey.toString()
+    );
 
-      console.log(
-        `${duration}ms (Base Layer) Delegate Auction Record completed`
-      );
-      assert.isTrue(success, "Failed to delegate Auction Record");
+    // Hand it back so the rest of the suite keeps working
+    await program.methods
+      .proposeAuthority(authority.publicKey)
+      .accountsPartial({ authority: dsp1Owner.publicKey, adwConfig })
+      .signers([dsp1Owner])
+      .rpc();
+    await program.methods
+      .acceptAuthority()
+      .accountsPartial({
+        newAuthority: authority.publicKey,
+        adwConfig,
+        exchangeVaultState,
+      })
+      .rpc();
+
+    config = await program.account.protocolConfig.fetch(adwConfig);
+    vaultState = await program.account.exchangeVault.fetch(exchangeVaultState);
+    assert.equal(config.authority.toString(), authority.publicKey.toString());
+    assert.equal(
+      vaultState.authority.toString(),
+      authority.publicKey.toString()
+    );
 
-      console.log("Auction Record delegated to ephemeral rollup successfully");
-    } catch (e) {
-      console.error("Failed to delegate Auction Record:", e);
-      throw e;
-    }
+    console.log("Protocol authority rotated successfully");
   });
 
-  it("Records DSP token balances before bidding", async function () {
-    this.timeout(10000);
-
-    console.log("Recording initial DSP token balances...");
+  // Test creating ad request
+  it("Approves and revokes an operator", async function () {
+    this.timeout(30000);
 
-    // Get DSP1 token balance
-    dsp1TokenBalanceBefore = await getTokenBalance(dsp1TokenAccount);
-    console.log(
-      `DSP1 initial token balance: ${dsp1TokenBalanceBefore.toString()}`
+    const operator = anchor.web3.Keypair.generate().publicKey;
+    const [operatorState] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("operator"), operator.toBuffer()],
+      program.programId
     );
 
-    // Get DSP2 token balance
-    dsp2TokenBalanceBefore = await getTokenBalance(dsp2TokenAccount);
-    console.log(
-      `DSP2 initial token balance: ${dsp2TokenBalanceBefore.toString()}`
+    await program.methods
+      .addOperator(operator)
+      .accountsPartial({
+        authority: authority.publicKey,
+        adwConfig,
+        operatorState,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const operatorAccount = await program.account.operator.fetch(
+      operatorState
     );
+    assert.equal(operatorAccount.operator.toString(), operator.toString());
 
-    // Verify we have valid balances
-    assert.doesNotThrow(() => {
-      BigInt(dsp1TokenBalanceBefore.toString());
-      BigInt(dsp2TokenBalanceBefore.toString());
-    }, "Failed to get initial token balances");
-  });
+    // Only the protocol authority manages operators
+    try {
+      await program.methods
+        .removeOperator(operator)
+        .accountsPartial({
+          authority: dsp1Owner.publicKey,
+          adwConfig,
+          operatorState,
+        })
+        .signers([dsp1Owner])
+        .rpc();
+      assert.fail("Non-authority should not remove an operator");
+    } catch (e) {
+      assert.include(e.toString(), "UnauthorizedAccess");
+    }
 
-  it("Creates and delegates ad responses", async function () {
-    this.timeout(60000);
+    await program.methods
+      .removeOperator(operator)
+      .accountsPartial({
+        authority: authority.publicKey,
+        adwConfig,
+        operatorState,
+      })
+      .rpc();
 
-    // Skip if ephemeral rollup not connected
-    if (!ephemeralRollupConnected) {
-      console.warn(
-        "⚠️ Skipping ad response test: Ephemeral Rollup not connected"
-      );
-      this.skip();
-    }
+    assert.isNull(await provider.connection.getAccountInfo(operatorState));
+  });
+
+  it("Gates auction cranking on the authority or an approved operator", async function () {
+    this.timeout(30000);
+
+    const { record } = await placeStandaloneAsk(requestTtl);
+
+    // Neither the authority nor an operator
+    try {
+      await program.methods
+        .processAuctionResults(Array.from(new Uint8Array(32)))
+        .accountsPartial({
+          authority: dsp1Owner.publicKey,
+          operator: null,
+          auctionRecord: record,
+          publisher: publisherState,
+          adwConfig,
+          exchangeVaultState,
+        })
+        .signers([dsp1Owner])
+        .rpc();
+      assert.fail("Unapproved signer should not process auction results");
+    } catch (e) {
+      assert.include(e.toString(), "UnauthorizedAccess");
+    }
+
+    try {
+      await program.methods
+        .settleAuction()
+        .accountsPartial({
+          authority: dsp1Owner.publicKey,
+          operator: null,
+          auctionRecord: record,
+          publisher: publisherState,
+          adwConfig,
+          dsp: dsp1,
+          exchangeVaultState,
+          exchangeVault,
+          publisherTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([dsp1Owner])
+        .rpc();
+      assert.fail("Unapproved signer should not settle an auction");
+    } catch (e) {
+      assert.include(e.toString(), "UnauthorizedAccess");
+    }
+
+    // Approve the operator used for the main auction's results and settlement
+    [crankOperatorState] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("operator"), crankOperator.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .addOperator(crankOperator.publicKey)
+      .accountsPartial({
+        authority: authority.publicKey,
+        adwConfig,
+        operatorState: crankOperatorState,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    // An approved operator gets past the gate, this record just hasn't been auctioned yet
+    try {
+      await program.methods
+        .processAuctionResults(Array.from(new Uint8Array(32)))
+        .accountsPartial({
+          authority: crankOperator.publicKey,
+          operator: crankOperatorState,
+          auctionRecord: record,
+          publisher: publisherState,
+          adwConfig,
+          exchangeVaultState,
+        })
+        .signers([crankOperator])
+        .rpc();
+      assert.fail("Record is not cleared yet");
+    } catch (e) {
+      assert.notInclude(e.toString(), "UnauthorizedAccess");
+    }
+  });
+
+  it("Rejects auction steps taken out of order", async function () {
+    this.timeout(30000);
+
+    const { id, record } = await placeStandaloneAsk(requestTtl);
+
+    // Results can only be processed once the rollup has cleared the auction
+    try {
+      await program.methods
+        .processAuctionResults(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          auctionRecord: record,
+          publisher: publisherState,
+          adwConfig,
+          exchangeVaultState,
+        })
+        .rpc();
+      assert.fail("Uncleared auction should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "AuctionNotCleared");
+    }
+
+    // Settlement needs the results to be accounted first
+    try {
+      await program.methods
+        .settleAuction()
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          auctionRecord: record,
+          publisher: publisherState,
+          adwConfig,
+          dsp: dsp1,
+          exchangeVaultState,
+          exchangeVault,
+          publisherTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Unaccounted auction should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "AuctionNotAccounted");
+    }
+
+    const recordAccount = await program.account.auctionRecord.fetch(record);
+    assert.deepEqual(recordAccount.auctionState, { created: {} });
+  });
+
+  it("Creates an ad request", async function () {
+    this.timeout(30000);
+
+    const floorPrice = new BN(1000000); // 1 token with 6 decimals
+
+    // TTLs outside the configured bounds are rejected
+    try {
+      await program.methods
+        .placeAdAsk(
+          adRequestId,
+          floorPrice,
+          maxRequestTtl.addn(1),
+          auctionType
+        )
+        .accountsPartial({
+          publisher: publisherOwner.publicKey,
+          publisherState,
+          auctionRecord,
+          adwConfig,
+          adRequest,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([publisherOwner])
+        .rpc();
+      assert.fail("TTL above the configured maximum should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "InvalidRequestTtl");
+    }
+
+    try {
+      // Create ad request
+      const tx = await program.methods
+        .placeAdAsk(adRequestId, floorPrice, requestTtl, auctionType)
+        .accountsPartial({
+          publisher: publisherOwner.publicKey,
+          publisherState,
+          auctionRecord,
+          adwConfig,
+          adRequest,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([publisherOwner])
+        .rpc();
+
+      console.log("Ad request created, txHash:", tx);
+
+      // Verify ad request
+      const adRequestAccount = await program.account.adRequest.fetch(adRequest);
+
+      console.log("the passed ad request", adRequestAccount);
+
+      assert.equal(
+        adRequestAccount.publisher.toString(),
+        publisherOwner.publicKey.toString()
+      );
+      assert.equal(
+        adRequestAccount.floorPrice.toString(),
+        floorPrice.toString()
+      );
+      assert.deepEqual(adRequestAccount.auctionType, auctionType);
+
+      const auctionRecordAccount = await program.account.auctionRecord.fetch(
+        auctionRecord
+      );
+      assert.deepEqual(auctionRecordAccount.auctionType, auctionType);
+      assert.deepEqual(auctionRecordAccount.auctionState, { created: {} });
+
+      // Verify expiration time is roughly the requested TTL from now
+      const currentTime = Math.floor(Date.now() / 1000);
+      const expectedExpiration = currentTime + requestTtl.toNumber();
+      assert.approximately(
+        adRequestAccount.expiration.toNumber(),
+        expectedExpiration,
+        300 // Allow 5 min difference for processing time
+      );
+
+      console.log("Ad request created successfully");
+    } catch (e) {
+      console.error("Failed to create ad request:", e);
+      throw e;
+    }
+  });
+
+  it("Records DSP token balances before bidding", async function () {
+    this.timeout(10000);
+
+    console.log("Recording initial DSP token balances...");
+
+    // Get DSP1 token balance
+    dsp1TokenBalanceBefore = await getTokenBalance(dsp1TokenAccount);
+    console.log(
+      `DSP1 initial token balance: ${dsp1TokenBalanceBefore.toString()}`
+    );
+
+    // Get DSP2 token balance
+    dsp2TokenBalanceBefore = await getTokenBalance(dsp2TokenAccount);
+    console.log(
+      `DSP2 initial token balance: ${dsp2TokenBalanceBefore.toString()}`
+    );
+
+    // Verify we have valid balances
+    assert.doesNotThrow(() => {
+      BigInt(dsp1TokenBalanceBefore.toString());
+      BigInt(dsp2TokenBalanceBefore.toString());
+    }, "Failed to get initial token balances");
+  });
+
+  it("Deposits DSP prepaid funds", async function () {
+    this.timeout(30000);
+
+    for (const [owner, dspState, tokenAccount] of [
+      [dsp1Owner, dsp1, dsp1TokenAccount],
+      [dsp2Owner, dsp2, dsp2TokenAccount],
+    ] as [Keypair, PublicKey, PublicKey][]) {
+      const balanceBefore = (await program.account.dsp.fetch(dspState))
+        .balance;
+
+      const tx = await program.methods
+        .depositDspFunds(dspDepositAmount)
+        .accountsPartial({
+          dsp: owner.publicKey,
+          dspState,
+          adwConfig,
+          exchangeVaultState,
+          exchangeVault,
+          dspTokenAccount: tokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+
+      console.log(`Deposited for ${owner.publicKey.toString()}, txHash:`, tx);
+
+      const dspAccount = await program.account.dsp.fetch(dspState);
+      assert.equal(
+        dspAccount.balance.toString(),
+        balanceBefore.add(dspDepositAmount).toString(),
+        "Prepaid balance should grow by the deposit"
+      );
+    }
+
+    console.log("DSP funds deposited successfully");
+  });
+
+  it("Rejects bids below the floor price", async function () {
+    this.timeout(30000);
+
+    try {
+      await program.methods
+        .placeAdBid(adRequestId, new BN(1), creative1Id)
+        .accountsPartial({
+          dsp: dsp1Owner.publicKey,
+          dspState: dsp1,
+          adDspResponse: adResponse1,
+          adRequest,
+          adwConfig,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([dsp1Owner])
+        .rpc();
+      assert.fail("Bid below the floor price should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "BidBelowFloorPrice");
+    }
+  });
+
+  it("Expires a stale ad request and releases its bids", async function () {
+    this.timeout(30000);
+
+    const { id, request, record } = await placeStandaloneAsk(minRequestTtl);
+    const { response } = await placeStandaloneBid(id, request, new BN(1000000));
+
+    const lockedBefore = (await program.account.dsp.fetch(dsp1)).lockedAmount;
+
+    // Let the request run past its expiration
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    const tx = await program.methods
+      .expireAdRequest(id)
+      .accountsPartial({
+        caller: authority.publicKey,
+        publisher: publisherOwner.publicKey,
+        adRequest: request,
+        auctionRecord: record,
+      })
+      .remainingAccounts([
+        { pubkey: response, isSigner: false, isWritable: true },
+        { pubkey: dsp1, isSigner: false, isWritable: true },
+      ])
+      .rpc();
+
+    console.log("Ad request expired, txHash:", tx);
+
+    const dspAccount = await program.account.dsp.fetch(dsp1);
+    assert.equal(
+      dspAccount.lockedAmount.toString(),
+      lockedBefore.subn(1000000).toString(),
+      "Expired bid should be unlocked"
+    );
+
+    const responseAccount = await program.account.adResponse.fetch(response);
+    assert.deepEqual(responseAccount.status, { refunded: {} });
+
+    assert.isNull(await provider.connection.getAccountInfo(request));
+    assert.isNull(await provider.connection.getAccountInfo(record));
+  });
+
+  it("Cancels an open ad request and closes its released bids", async function () {
+    this.timeout(30000);
+
+    const { id, request, record } = await placeStandaloneAsk(requestTtl);
+    const { creativeId, response } = await placeStandaloneBid(
+      id,
+      request,
+      new BN(1000000)
+    );
+
+    const lockedBefore = (await program.account.dsp.fetch(dsp1)).lockedAmount;
+
+    const tx = await program.methods
+      .cancelAdAsk(id)
+      .accountsPartial({
+        publisher: publisherOwner.publicKey,
+        adRequest: request,
+        auctionRecord: record,
+      })
+      .remainingAccounts([
+        { pubkey: response, isSigner: false, isWritable: true },
+        { pubkey: dsp1, isSigner: false, isWritable: true },
+      ])
+      .signers([publisherOwner])
+      .rpc();
+
+    console.log("Ad request cancelled, txHash:", tx);
+
+    const dspAccount = await program.account.dsp.fetch(dsp1);
+    assert.equal(
+      dspAccount.lockedAmount.toString(),
+      lockedBefore.subn(1000000).toString(),
+      "Cancelled bid should be unlocked"
+    );
+    assert.isNull(await provider.connection.getAccountInfo(request));
+    assert.isNull(await provider.connection.getAccountInfo(record));
+
+    // The DSP reclaims the rent on its released response
+    await program.methods
+      .closeAdResponse(id, creativeId)
+      .accountsPartial({
+        dsp: dsp1Owner.publicKey,
+        adResponse: response,
+      })
+      .signers([dsp1Owner])
+      .rpc();
+
+    assert.isNull(await provider.connection.getAccountInfo(response));
+  });
+
+  it("Amends and retracts a bid before the auction", async function () {
+    this.timeout(30000);
+
+    const { id, request, record } = await placeStandaloneAsk(requestTtl);
+    const { creativeId, response } = await placeStandaloneBid(
+      id,
+      request,
+      new BN(1000000)
+    );
+
+    const lockedBefore = (await program.account.dsp.fetch(dsp1)).lockedAmount;
+
+    // Raising the bid locks the difference
+    await program.methods
+      .updateAdBid(id, creativeId, new BN(1500000))
+      .accountsPartial({
+        dsp: dsp1Owner.publicKey,
+        dspState: dsp1,
+        adResponse: response,
+        adRequest: request,
+        adwConfig,
+      })
+      .signers([dsp1Owner])
+      .rpc();
+
+    let dspAccount = await program.account.dsp.fetch(dsp1);
+    assert.equal(
+      dspAccount.lockedAmount.toString(),
+      lockedBefore.addn(500000).toString(),
+      "Raised bid should lock the difference"
+    );
+    const responseAccount = await program.account.adResponse.fetch(response);
+    assert.equal(responseAccount.bidAmount.toString(), "1500000");
+
+    // Retracting unlocks the full bid and closes the response
+    await program.methods
+      .retractAdBid(id, creativeId)
+      .accountsPartial({
+        dsp: dsp1Owner.publicKey,
+        dspState: dsp1,
+        adResponse: response,
+        adRequest: request,
+      })
+      .signers([dsp1Owner])
+      .rpc();
+
+    dspAccount = await program.account.dsp.fetch(dsp1);
+    assert.equal(
+      dspAccount.lockedAmount.toString(),
+      lockedBefore.subn(1000000).toString(),
+      "Retracted bid should be unlocked"
+    );
+    assert.isNull(await provider.connection.getAccountInfo(response));
+
+    // Clean up the now empty request
+    await program.methods
+      .cancelAdAsk(id)
+      .accountsPartial({
+        publisher: publisherOwner.publicKey,
+        adRequest: request,
+        auctionRecord: record,
+      })
+      .signers([publisherOwner])
+      .rpc();
+  });
+
+  it("Creates and delegates ad responses", async function () {
+    this.timeout(60000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn(
+        "⚠️ Skipping ad response test: Ephemeral Rollup not connected"
+      );
+      this.skip();
+    }
 
     try {
       // DSP 1 response (higher bid)
@@ -764,6 +1614,7 @@ describe("poynt-adw3", () => {
         [
           Buffer.from("ad_response"),
           dsp1Owner.publicKey.toBuffer(),
+          Buffer.from(adRequestId),
           Buffer.from(creative1Id),
         ],
         program.programId
@@ -781,13 +1632,9 @@ describe("poynt-adw3", () => {
           dsp: dsp1Owner.publicKey,
           dspState: dsp1,
           adDspResponse: newAdResponse1,
+          adRequest,
           adwConfig,
-          exchangeVaultState,
-          exchangeVault,
-          dspTokenAccount: dsp1TokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: anchor.web3.SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         })
         .transaction();
       // Set fee payer explicitly
@@ -855,6 +1702,7 @@ describe("poynt-adw3", () => {
         [
           Buffer.from("ad_response"),
           dsp2Owner.publicKey.toBuffer(),
+          Buffer.from(adRequestId),
           Buffer.from(creative2Id),
         ],
         program.programId
@@ -874,13 +1722,9 @@ describe("poynt-adw3", () => {
           dsp: dsp2Owner.publicKey,
           dspState: dsp2,
           adDspResponse: newAdResponse2,
+          adRequest,
           adwConfig,
-          exchangeVaultState,
-          exchangeVault,
-          dspTokenAccount: dsp2TokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: anchor.web3.SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         })
         .transaction();
       // Set fee payer explicitly
@@ -960,6 +1804,11 @@ describe("poynt-adw3", () => {
           bidAmount1.toString(),
           "Bid amount mismatch in response 1"
         );
+        assert.equal(
+          response1.adRequest.toString(),
+          adRequest.toString(),
+          "Ad request mismatch in response 1"
+        );
         console.log("✅ Response 1 verified successfully");
       } catch (e) {
         console.error("❌ Failed to verify response 1:", e.message);
@@ -996,6 +1845,69 @@ describe("poynt-adw3", () => {
     }
   });
 
+  // Test delegating accounts to ephemeral rollup
+  it("Delegates ad request to ephemeral rollup", async function () {
+    this.timeout(60000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn(
+        "⚠️ Skipping delegation test: Ephemeral Rollup not connected"
+      );
+      this.skip();
+    }
+
+    // Both bids are registered on the request, process_auction must see all of them
+    const requestBeforeDelegation = await program.account.adRequest.fetch(
+      adRequest
+    );
+    assert.equal(requestBeforeDelegation.bidCount, 2);
+
+    try {
+      // Delegate ad request
+      const start = Date.now();
+      const success = await delegateAdRequest(adRequest, adRequestId);
+      const duration = Date.now() - start;
+
+      console.log(`${duration}ms (Base Layer) Delegate Ad Request completed`);
+      assert.isTrue(success, "Failed to delegate ad request");
+
+      console.log("Ad request delegated to ephemeral rollup successfully");
+    } catch (e) {
+      console.error("Failed to delegate ad request:", e);
+      throw e;
+    }
+  });
+
+  it("Delegates auction Record to ephemeral rollup", async function () {
+    this.timeout(60000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn(
+        "⚠️ Skipping delegation test: Ephemeral Rollup not connected"
+      );
+      this.skip();
+    }
+
+    try {
+      // Delegate ad request
+      const start = Date.now();
+      const success = await delegateAuctionRecord(adRequestId);
+      const duration = Date.now() - start;
+
+      console.log(
+        `${duration}ms (Base Layer) Delegate Auction Record completed`
+      );
+      assert.isTrue(success, "Failed to delegate Auction Record");
+
+      console.log("Auction Record delegated to ephemeral rollup successfully");
+    } catch (e) {
+      console.error("Failed to delegate Auction Record:", e);
+      throw e;
+    }
+  });
+
   // Process auction in ephemeral rollups - following the counter example pattern
   it("Processes the auction in ephemeral rollups", async function () {
     this.timeout(60000);
@@ -1023,6 +1935,7 @@ describe("poynt-adw3", () => {
         .processAuction(adRequestId)
         .accountsPartial({
           authority: authority.publicKey,
+          operator: null,
           adRequest,
           publisher: publisherState,
           adwConfig: adwConfig,
@@ -1089,7 +2002,7 @@ describe("poynt-adw3", () => {
           auctionRecordAccount.winningDsp.toString(),
           dsp1.toString()
         );
-        assert.equal(auctionRecordAccount.isSettled, false);
+        assert.deepEqual(auctionRecordAccount.auctionState, { cleared: {} });
       } catch (e) {
         console.log(
           "Could not fetch auction record yet. This is expected in ephemeral rollups until committed to base layer."
@@ -1103,6 +2016,88 @@ describe("poynt-adw3", () => {
     }
   });
 
+  it("Records a no-fill auction in ephemeral rollups", async function () {
+    this.timeout(120000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn("⚠️ Skipping no-fill test: Ephemeral Rollup not connected");
+      this.skip();
+    }
+
+    const { id, request, record } = await placeStandaloneAsk(requestTtl);
+
+    assert.isTrue(await delegateAdRequest(request, id));
+    assert.isTrue(await delegateAuctionRecord(id, record));
+
+    // No responses at all, the auction can only end unfilled
+    const txHash = await sendToEphemeralRollup(
+      await program.methods
+        .processAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          adRequest: request,
+          publisher: publisherState,
+          adwConfig,
+          auctionRecord: record,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .transaction()
+    );
+    console.log(`(ER) No-fill Process Auction txHash: ${txHash}`);
+
+    const info = await providerEphemeralRollup.connection.getAccountInfo(
+      record
+    );
+    const recordAccount = program.coder.accounts.decode(
+      "auctionRecord",
+      info.data
+    );
+    assert.deepEqual(recordAccount.auctionState, { noFill: {} });
+    assert.isNull(recordAccount.winningDsp);
+
+    // Bring the record back to the base layer, there is nothing to settle on it
+    await sendToEphemeralRollup(
+      await program.methods
+        .undelegateAuctionRecordAfterAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          publisher: publisherOwner.publicKey,
+          auctionRecord: record,
+        })
+        .transaction()
+    );
+    for (let attempt = 0; attempt < 30; attempt++) {
+      const info = await provider.connection.getAccountInfo(record);
+      if (info?.owner.equals(program.programId)) break;
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+    }
+
+    try {
+      await program.methods
+        .settleAuction()
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          auctionRecord: record,
+          publisher: publisherState,
+          adwConfig,
+          dsp: dsp1,
+          exchangeVaultState,
+          exchangeVault,
+          publisherTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("A no-fill auction should have nothing to settle");
+    } catch (e) {
+      assert.include(e.toString(), "NothingToSettle");
+    }
+  });
+
   it("Process Auction Results", async function () {
     this.timeout(60000);
 
@@ -1114,11 +2109,12 @@ describe("poynt-adw3", () => {
       console.log("Auction record address:", auctionRecord.toString());
       console.log("Publisher address:", publisherOwner.publicKey.toString());
 
-      // Build transaction with program and send with the right wallet
+      // Cranked by the approved operator rather than the protocol authority
       let tx = await program.methods
         .processAuctionResults(adRequestId)
         .accountsPartial({
-          authority: authority.publicKey,
+          authority: crankOperator.publicKey,
+          operator: crankOperatorState,
           auctionRecord,
           publisher: publisherState,
           adwConfig,
@@ -1136,6 +2132,7 @@ describe("poynt-adw3", () => {
 
       // Sign with the wallet
       tx = await wallet.signTransaction(tx);
+      tx.partialSign(crankOperator);
 
       // Use the base connection for this transaction (not ER connection)
       const txHash = await provider.connection.sendRawTransaction(
@@ -1148,6 +2145,11 @@ describe("poynt-adw3", () => {
 
       await provider.connection.confirmTransaction(txHash, "confirmed");
 
+      const recordAccount = await program.account.auctionRecord.fetch(
+        auctionRecord
+      );
+      assert.deepEqual(recordAccount.auctionState, { accounted: {} });
+
       console.log(`Process Auction Results: ${txHash}`);
       const duration = Date.now() - start;
       console.log(
@@ -1280,7 +2282,7 @@ describe("poynt-adw3", () => {
       // Undelegate ad response 1 - using counter example pattern
       console.log("Undelegating ad response 1...");
       let tx1 = await program.methods
-        .undelegateResponseAfterAuction(creative1Id)
+        .undelegateResponseAfterAuction(adRequestId, creative1Id)
         .accountsPartial({
           authority: authority.publicKey,
           adResponse: adResponse1,
@@ -1314,7 +2316,7 @@ describe("poynt-adw3", () => {
       // Undelegate ad response 2
       console.log("Undelegating ad response 2...");
       let tx2 = await program.methods
-        .undelegateResponseAfterAuction(creative2Id)
+        .undelegateResponseAfterAuction(adRequestId, creative2Id)
         .accountsPartial({
           authority: authority.publicKey,
           adResponse: adResponse2,
@@ -1415,55 +2417,197 @@ describe("poynt-adw3", () => {
     this.timeout(30000);
 
     try {
-      // Settle auction
-      console.log("Settling auction...");
-      console.log("authority:", authority.publicKey.toString());
-      console.log("auctionRecord:", auctionRecord.toString());
-      console.log("publisher:", publisherState.toString());
-      console.log("dsp:", dsp1.toString());
-      console.log("exchangeVaultState:", exchangeVaultState.toString());
-      console.log("exchangeVault:", exchangeVault.toString());
-      console.log("publisherTokenAccount:", publisherTokenAccount.toString());
-
-      const tx = await program.methods
-        .settleAuction()
+      // Settle auction
+      console.log("Settling auction...");
+      console.log("authority:", authority.publicKey.toString());
+      console.log("auctionRecord:", auctionRecord.toString());
+      console.log("publisher:", publisherState.toString());
+      console.log("dsp:", dsp1.toString());
+      console.log("exchangeVaultState:", exchangeVaultState.toString());
+      console.log("exchangeVault:", exchangeVault.toString());
+      console.log("publisherTokenAccount:", publisherTokenAccount.toString());
+
+      // Settled by the approved operator
+      const tx = await program.methods
+        .settleAuction()
+        .accountsPartial({
+          authority: crankOperator.publicKey,
+          operator: crankOperatorState,
+          auctionRecord: auctionRecord,
+          publisher: publisherState,
+          adwConfig,
+          dsp: dsp1,
+          exchangeVaultState: exchangeVaultState,
+          exchangeVault: exchangeVault,
+          publisherTokenAccount: publisherTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([crankOperator])
+        .rpc({ skipPreflight: true });
+
+      console.log("Auction settled, txHash:", tx);
+
+      // Verify auction record is settled
+      const auctionRecordAccount = await program.account.auctionRecord.fetch(
+        auctionRecord
+      );
+      assert.deepEqual(auctionRecordAccount.auctionState, { settled: {} });
+
+      // Verify publisher received payment
+      const publisherAccount = await program.account.publisher.fetch(
+        publisherState
+      );
+      console.log(
+        `Publisher revenue: ${publisherAccount.totalRevenue.toString()}`
+      );
+      assert.isAbove(publisherAccount.totalRevenue.toNumber(), 0);
+
+      console.log("Auction settled successfully");
+    } catch (e) {
+      console.error("Failed to settle auction:", e);
+      console.log(
+        "This may be expected if auction delegation failed. Continuing to final verification."
+      );
+    }
+  });
+
+  it("Rejects malformed and already settled batch settlements", async function () {
+    this.timeout(30000);
+
+    const batchAccounts = {
+      authority: authority.publicKey,
+      operator: null,
+      adwConfig,
+      exchangeVaultState,
+      exchangeVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // auction_count must cover the supplied triples
+    try {
+      await program.methods
+        .settleAuctionsBatch(2)
+        .accountsPartial(batchAccounts)
+        .remainingAccounts([
+          { pubkey: auctionRecord, isSigner: false, isWritable: true },
+          { pubkey: publisherState, isSigner: false, isWritable: false },
+          { pubkey: dsp1, isSigner: false, isWritable: true },
+        ])
+        .rpc();
+      assert.fail("Short batch should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "InvalidBatchAccounts");
+    }
+
+    const record = await program.account.auctionRecord.fetch(auctionRecord);
+    if (!("settled" in record.auctionState)) {
+      console.warn("⚠️ Skipping resettlement check: auction not settled");
+      return;
+    }
+
+    // A settled auction can't be paid out a second time through the batch
+    try {
+      await program.methods
+        .settleAuctionsBatch(1)
+        .accountsPartial(batchAccounts)
+        .remainingAccounts([
+          { pubkey: auctionRecord, isSigner: false, isWritable: true },
+          { pubkey: publisherState, isSigner: false, isWritable: false },
+          { pubkey: dsp1, isSigner: false, isWritable: true },
+          { pubkey: publisherTokenAccount, isSigner: false, isWritable: true },
+        ])
+        .rpc();
+      assert.fail("Settled auction should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "AuctionAlreadySettled");
+    }
+  });
+
+  it("Refunds the losing bid", async function () {
+    this.timeout(30000);
+
+    // DSP 2 placed the lower bid, so it should have lost the auction
+    const response2Info = await provider.connection.getAccountInfo(adResponse2);
+    const response2 = await program.account.adResponse
+      .fetch(adResponse2)
+      .catch(() => null);
+    if (
+      !response2 ||
+      !response2Info.owner.equals(program.programId) ||
+      !("loss" in response2.status)
+    ) {
+      console.warn(
+        "⚠️ Skipping refund test: Response 2 is not an undelegated losing bid"
+      );
+      this.skip();
+    }
+
+    const lockedBefore = (await program.account.dsp.fetch(dsp2)).lockedAmount;
+
+    const tx = await program.methods
+      .refundLosingBid(adRequestId, creative2Id)
+      .accountsPartial({
+        dsp: dsp2Owner.publicKey,
+        adResponse: adResponse2,
+        dspState: dsp2,
+      })
+      .signers([dsp2Owner])
+      .rpc();
+
+    console.log("Losing bid refunded, txHash:", tx);
+
+    const dsp2Account = await program.account.dsp.fetch(dsp2);
+    assert.equal(
+      lockedBefore.sub(dsp2Account.lockedAmount).toString(),
+      response2.bidAmount.toString(),
+      "DSP2's full bid should be unlocked"
+    );
+    assert.equal(
+      dsp2Account.balance.toString(),
+      dspDepositAmount.toString(),
+      "DSP2 should not be charged for a losing bid"
+    );
+
+    // The response account is closed once refunded
+    const closed = await provider.connection.getAccountInfo(adResponse2);
+    assert.isNull(closed, "Refunded response should be closed");
+
+    console.log("Losing bid refunded successfully");
+  });
+
+  it("Rejects legacy refunds without a legacy bid", async function () {
+    this.timeout(30000);
+
+    // Current responses live at a different address, nothing was escrowed here
+    const [legacyResponse] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ad_response"),
+        dsp1Owner.publicKey.toBuffer(),
+        Buffer.from(creative1Id),
+      ],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .refundLegacyBid(creative1Id)
         .accountsPartial({
-          authority: authority.publicKey,
-          auctionRecord: auctionRecord,
-          publisher: publisherState,
-          dsp: dsp1,
-          exchangeVaultState: exchangeVaultState,
-          exchangeVault: exchangeVault,
-          publisherTokenAccount: publisherTokenAccount,
+          dsp: dsp1Owner.publicKey,
+          legacyResponse,
+          exchangeVaultState,
+          exchangeVault,
+          dspTokenAccount: dsp1TokenAccount,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
           associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         })
-        .rpc({ skipPreflight: true });
-
-      console.log("Auction settled, txHash:", tx);
-
-      // Verify auction record is settled
-      const auctionRecordAccount = await program.account.auctionRecord.fetch(
-        auctionRecord
-      );
-      assert.equal(auctionRecordAccount.isSettled, true);
-
-      // Verify publisher received payment
-      const publisherAccount = await program.account.publisher.fetch(
-        publisherState
-      );
-      console.log(
-        `Publisher revenue: ${publisherAccount.totalRevenue.toString()}`
-      );
-      assert.isAbove(publisherAccount.totalRevenue.toNumber(), 0);
-
-      console.log("Auction settled successfully");
+        .signers([dsp1Owner])
+        .rpc();
+      assert.fail("Refund without a legacy bid should be rejected");
     } catch (e) {
-      console.error("Failed to settle auction:", e);
-      console.log(
-        "This may be expected if auction delegation failed. Continuing to final verification."
-      );
+      assert.include(e.toString(), "AccountOwnedByWrongProgram");
     }
   });
 
@@ -1484,9 +2628,17 @@ describe("poynt-adw3", () => {
       `DSP2 final token balance: ${dsp2TokenBalanceAfter.toString()}`
     );
 
-    // Calculate differences
-    const dsp1Difference = dsp1TokenBalanceBefore - dsp1TokenBalanceAfter;
-    const dsp2Difference = dsp2TokenBalanceBefore - dsp2TokenBalanceAfter;
+    // Calculate differences, funds still held as prepaid balance were not spent
+    const dsp1Prepaid = BigInt(
+      (await program.account.dsp.fetch(dsp1)).balance.toString()
+    );
+    const dsp2Prepaid = BigInt(
+      (await program.account.dsp.fetch(dsp2)).balance.toString()
+    );
+    const dsp1Difference =
+      dsp1TokenBalanceBefore - dsp1TokenBalanceAfter - dsp1Prepaid;
+    const dsp2Difference =
+      dsp2TokenBalanceBefore - dsp2TokenBalanceAfter - dsp2Prepaid;
 
     console.log(`DSP1 was debited: ${dsp1Difference.toString()} tokens`);
     console.log(`DSP2 was debited: ${dsp2Difference.toString()} tokens`);
@@ -1562,6 +2714,61 @@ describe("poynt-adw3", () => {
     }
   });
 
+  it("Withdraws DSP prepaid funds", async function () {
+    this.timeout(30000);
+
+    const dspBefore = await program.account.dsp.fetch(dsp2);
+    const tokensBefore = await getTokenBalance(dsp2TokenAccount);
+
+    // Withdrawing more than the prepaid balance must fail
+    try {
+      await program.methods
+        .withdrawDspFunds(dspBefore.balance.addn(1))
+        .accountsPartial({
+          dsp: dsp2Owner.publicKey,
+          dspState: dsp2,
+          adwConfig,
+          exchangeVaultState,
+          exchangeVault,
+          dspTokenAccount: dsp2TokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([dsp2Owner])
+        .rpc();
+      assert.fail("Withdrawal above balance should have been rejected");
+    } catch (e) {
+      assert.include(e.toString(), "ExcessiveWithdrawalAmount");
+    }
+
+    const tx = await program.methods
+      .withdrawDspFunds(dspBefore.balance)
+      .accountsPartial({
+        dsp: dsp2Owner.publicKey,
+        dspState: dsp2,
+        adwConfig,
+        exchangeVaultState,
+        exchangeVault,
+        dspTokenAccount: dsp2TokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([dsp2Owner])
+      .rpc();
+
+    console.log("DSP2 funds withdrawn, txHash:", tx);
+
+    const dspAfter = await program.account.dsp.fetch(dsp2);
+    const tokensAfter = await getTokenBalance(dsp2TokenAccount);
+    assert.equal(dspAfter.balance.toNumber(), 0);
+    assert.equal(
+      (tokensAfter - tokensBefore).toString(),
+      dspBefore.balance.toString()
+    );
+
+    console.log("DSP funds withdrawn successfully");
+  });
+
   it("Verifies final state of the protocol", async function () {
     this.timeout(30000);
 
@@ -1574,7 +2781,10 @@ describe("poynt-adw3", () => {
         );
 
         console.log("Auction Record Details:");
-        console.log("- Is settled:", auctionRecordAccount.isSettled);
+        console.log(
+          "- Auction state:",
+          Object.keys(auctionRecordAccount.auctionState)[0]
+        );
         console.log(
           "- Winning DSP:",
           auctionRecordAccount.winningDsp.toString()
@@ -1638,15 +2848,8 @@ describe("poynt-adw3", () => {
       console.log("DSP1 State:");
       console.log(JSON.stringify(dsp1Final, null, 2));
 
-      // Verify if DSP balance was reduced (payment was made)
-      if (dsp1Final.balance.toNumber() > 0) {
-        console.log(
-          "⚠️ DSP still has locked balance:",
-          dsp1Final.balance.toString()
-        );
-      } else {
-        console.log("✅ DSP balance is zero, suggesting payment was processed");
-      }
+      // Winner's prepaid balance should only be reduced by the clearing price
+      console.log("- Prepaid balance:", dsp1Final.balance.toString());
 
       // Verify exchange vault state
       const vaultFinal = await program.account.exchangeVault.fetch(
@@ -1693,7 +2896,7 @@ describe("poynt-adw3", () => {
       if (publisherFinal.totalRevenue.toNumber() > 0) {
         const expectedProtocolFees = Math.floor(
           publisherFinal.totalRevenue.toNumber() *
-            (platformFeePercentage / publisherRevShare)
+            (platformFeeBps / publisherRevShareBps)
         );
 
         assert.approximately(
@@ -1716,6 +2919,503 @@ describe("poynt-adw3", () => {
     }
   });
 
+  it("Withdraws platform fees to the treasury", async function () {
+    this.timeout(30000);
+
+    const vaultBefore = await program.account.exchangeVault.fetch(
+      exchangeVaultState
+    );
+    if (vaultBefore.feeBalance.toNumber() === 0) {
+      console.warn("⚠️ Skipping fee withdrawal test: no fees collected");
+      this.skip();
+    }
+
+    // Withdrawing more than the collected fees must fail
+    try {
+      await program.methods
+        .withdrawPlatformFees(vaultBefore.feeBalance.addn(1))
+        .accountsPartial({
+          authority: authority.publicKey,
+          exchangeVaultState,
+          exchangeVault,
+          treasury: authority.publicKey,
+          treasuryTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Withdrawal above the fee balance should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "ExcessiveWithdrawalAmount");
+    }
+
+    const treasuryBefore = await getTokenBalance(treasuryTokenAccount);
+
+    const tx = await program.methods
+      .withdrawPlatformFees(vaultBefore.feeBalance)
+      .accountsPartial({
+        authority: authority.publicKey,
+        exchangeVaultState,
+        exchangeVault,
+        treasury: authority.publicKey,
+        treasuryTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    console.log("Platform fees withdrawn, txHash:", tx);
+
+    const vaultAfter = await program.account.exchangeVault.fetch(
+      exchangeVaultState
+    );
+    const treasuryAfter = await getTokenBalance(treasuryTokenAccount);
+    assert.equal(vaultAfter.feeBalance.toNumber(), 0);
+    assert.equal(
+      (treasuryAfter - treasuryBefore).toString(),
+      vaultBefore.feeBalance.toString()
+    );
+
+    console.log("Platform fees withdrawn successfully");
+  });
+
+  it("Closes the settled auction accounts", async function () {
+    this.timeout(30000);
+
+    const record = await program.account.auctionRecord.fetch(auctionRecord);
+    if (!("settled" in record.auctionState)) {
+      console.warn("⚠️ Skipping close test: auction not settled");
+      this.skip();
+    }
+
+    const winner = record.winningDsp;
+    const winningResponse = winner?.equals(dsp1Owner.publicKey)
+      ? adResponse1
+      : winner?.equals(dsp2Owner.publicKey)
+      ? adResponse2
+      : null;
+
+    const tx = await program.methods
+      .closeSettledAuction(adRequestId)
+      .accountsPartial({
+        publisher: publisherOwner.publicKey,
+        adRequest,
+        auctionRecord,
+        winningDsp: winner,
+        winningResponse,
+      })
+      .signers([publisherOwner])
+      .rpc();
+
+    console.log("Settled auction closed, txHash:", tx);
+
+    assert.isNull(await provider.connection.getAccountInfo(adRequest));
+    assert.isNull(await provider.connection.getAccountInfo(auctionRecord));
+    if (winningResponse) {
+      assert.isNull(await provider.connection.getAccountInfo(winningResponse));
+    }
+  });
+
+  it("Clears each auction at the price of its clearing rule", async function () {
+    this.timeout(300000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn("⚠️ Skipping clearing price test: Ephemeral Rollup not connected");
+      this.skip();
+    }
+
+    await depositDsp1(new BN(5700000));
+
+    // Standalone asks have a 1,000,000 floor
+    const cases: [
+      string,
+      { firstPrice: {} } | { secondPrice: {} },
+      BN[],
+      BN
+    ][] = [
+      ["first-price pays its own bid", { firstPrice: {} }, [new BN(1500000)], new BN(1500000)],
+      ["lone second-price bid pays the floor", { secondPrice: {} }, [new BN(1500000)], new BN(1000000)],
+      [
+        "second-price pays the runner-up bid",
+        { secondPrice: {} },
+        [new BN(1500000), new BN(1200000)],
+        new BN(1200000),
+      ],
+    ];
+
+    for (const [label, type, amounts, expected] of cases) {
+      const { record } = await runAuctionToAccounted(amounts, type);
+      const recordAccount = await program.account.auctionRecord.fetch(record);
+
+      assert.deepEqual(recordAccount.auctionType, type, label);
+      assert.equal(recordAccount.bidAmount.toString(), "1500000", label);
+      assert.equal(
+        recordAccount.clearingPrice.toString(),
+        expected.toString(),
+        label
+      );
+    }
+  });
+
+  it("Charges the winner only what it owes when settling", async function () {
+    this.timeout(120000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn("⚠️ Skipping settlement charge test: Ephemeral Rollup not connected");
+      this.skip();
+    }
+
+    // Both bids come from dsp1, the runner-up sets a clearing price below the winning bid
+    await depositDsp1(new BN(2700000));
+    const { record } = await runAuctionToAccounted(
+      [new BN(1500000), new BN(1200000)],
+      { secondPrice: {} }
+    );
+    const recordAccount = await program.account.auctionRecord.fetch(record);
+
+    const settleAccounts = (dsp: PublicKey) => ({
+      authority: authority.publicKey,
+      operator: null,
+      auctionRecord: record,
+      publisher: publisherState,
+      adwConfig,
+      dsp,
+      exchangeVaultState,
+      exchangeVault,
+      publisherTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    });
+
+    // Settlement can't be booked against a DSP that didn't win
+    try {
+      await program.methods
+        .settleAuction()
+        .accountsPartial(settleAccounts(dsp2))
+        .rpc();
+      assert.fail("Settling against a DSP that didn't win should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "InvalidDSP");
+    }
+
+    const dspBefore = await program.account.dsp.fetch(dsp1);
+    await program.methods
+      .settleAuction()
+      .accountsPartial(settleAccounts(dsp1))
+      .rpc();
+    const dspAfter = await program.account.dsp.fetch(dsp1);
+
+    // Winner pays the clearing price, less a remainder handed back to it
+    const charged =
+      "refundDsp" in recordAccount.remainderPolicy
+        ? recordAccount.clearingPrice.sub(recordAccount.remainder)
+        : recordAccount.clearingPrice;
+    assert.equal(recordAccount.clearingPrice.toString(), "1200000");
+    assert.equal(
+      dspBefore.balance.sub(dspAfter.balance).toString(),
+      charged.toString(),
+      "DSP1 should only be debited the clearing price"
+    );
+    assert.equal(
+      dspAfter.totalSpent.sub(dspBefore.totalSpent).toString(),
+      charged.toString(),
+      "DSP1 spend total should track the amount charged"
+    );
+
+    // The whole winning bid is released, the runner-up stays locked until refunded
+    assert.equal(
+      dspBefore.lockedAmount.sub(dspAfter.lockedAmount).toString(),
+      recordAccount.bidAmount.toString()
+    );
+
+    const settled = await program.account.auctionRecord.fetch(record);
+    assert.deepEqual(settled.auctionState, { settled: {} });
+
+    // The record is final once settled
+    try {
+      await program.methods
+        .settleAuction()
+        .accountsPartial(settleAccounts(dsp1))
+        .rpc();
+      assert.fail("A settled auction should not be settled again");
+    } catch (e) {
+      assert.include(e.toString(), "AuctionAlreadySettled");
+    }
+  });
+
+  it("Settles several auctions for one publisher in a single batch", async function () {
+    this.timeout(240000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn("⚠️ Skipping batch settlement: Ephemeral Rollup not connected");
+      this.skip();
+    }
+
+    // Top up dsp1 so both bids fit whatever the main auction charged
+    await depositDsp1(new BN(2500000));
+
+    // Both auctions belong to the same publisher, so they share a payment address
+    const auctions = [
+      await runAuctionToAccounted([new BN(1000000)]),
+      await runAuctionToAccounted([new BN(1500000)]),
+    ];
+
+    const records = await Promise.all(
+      auctions.map(({ record }) => program.account.auctionRecord.fetch(record))
+    );
+    for (const record of records) {
+      assert.deepEqual(record.auctionState, { accounted: {} });
+    }
+    const owed = records.reduce(
+      (sum, record) => sum + BigInt(record.publisherPayment.toString()),
+      BigInt(0)
+    );
+    const bidsLocked = records.reduce(
+      (sum, record) => sum.add(record.bidAmount),
+      new BN(0)
+    );
+
+    const publisherBalanceBefore = await getTokenBalance(publisherTokenAccount);
+    const vaultBalanceBefore = await getTokenBalance(exchangeVault);
+    const dspBefore = await program.account.dsp.fetch(dsp1);
+
+    let settledEvent = null;
+    const listener = program.addEventListener("auctionsSettled", (event) => {
+      settledEvent = event;
+    });
+
+    const tx = await program.methods
+      .settleAuctionsBatch(auctions.length)
+      .accountsPartial({
+        authority: authority.publicKey,
+        operator: null,
+        adwConfig,
+        exchangeVaultState,
+        exchangeVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        ...auctions.flatMap(({ record }) => [
+          { pubkey: record, isSigner: false, isWritable: true },
+          { pubkey: publisherState, isSigner: false, isWritable: false },
+          { pubkey: dsp1, isSigner: false, isWritable: true },
+        ]),
+        { pubkey: publisherTokenAccount, isSigner: false, isWritable: true },
+      ])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Batch settled, txHash:", tx);
+
+    // Give the listener time to receive the event
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    await program.removeEventListener(listener);
+
+    // A single transfer pays the publisher's combined share of both auctions
+    assert.equal(
+      (await getTokenBalance(publisherTokenAccount)) - publisherBalanceBefore,
+      owed
+    );
+    assert.equal(
+      vaultBalanceBefore - (await getTokenBalance(exchangeVault)),
+      owed
+    );
+
+    assert.isNotNull(settledEvent, "AuctionsSettled should be emitted");
+    assert.equal(settledEvent.auctionsSettled, 2);
+    assert.equal(settledEvent.publishersPaid, 1);
+    assert.equal(settledEvent.totalPaid.toString(), owed.toString());
+
+    for (const { record } of auctions) {
+      const recordAccount = await program.account.auctionRecord.fetch(record);
+      assert.deepEqual(recordAccount.auctionState, { settled: {} });
+    }
+
+    // Both winning bids are released from dsp1's escrow
+    const dspAfter = await program.account.dsp.fetch(dsp1);
+    assert.equal(
+      dspBefore.lockedAmount.sub(dspAfter.lockedAmount).toString(),
+      bidsLocked.toString()
+    );
+  });
+
+  it("Rejects process_auction when a bid is left out", async function () {
+    this.timeout(60000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn("⚠️ Skipping missing bid test: Ephemeral Rollup not connected");
+      this.skip();
+    }
+
+    // Runs last, these bids stay locked in the rollup once the auction is refused
+    await depositDsp1(new BN(2000000));
+
+    const { id, request, record } = await placeStandaloneAsk(requestTtl);
+    const first = await placeStandaloneBid(id, request, new BN(1000000));
+    const second = await placeStandaloneBid(id, request, new BN(1000000));
+
+    assert.equal((await program.account.adRequest.fetch(request)).bidCount, 2);
+
+    assert.isTrue(await delegateAdRequest(request, id));
+    assert.isTrue(await delegateAuctionRecord(id, record));
+    assert.isTrue(
+      await delegateAdResponse(first.creativeId, first.response, dsp1Owner.publicKey, id)
+    );
+    assert.isTrue(
+      await delegateAdResponse(second.creativeId, second.response, dsp1Owner.publicKey, id)
+    );
+
+    // Only the first bid is supplied, the second one is withheld
+    let tx = await program.methods
+      .processAuction(id)
+      .accountsPartial({
+        authority: authority.publicKey,
+        operator: null,
+        adRequest: request,
+        publisher: publisherState,
+        adwConfig,
+        auctionRecord: record,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: first.response, isSigner: false, isWritable: true },
+      ])
+      .transaction();
+    tx.feePayer = wallet.publicKey;
+    tx.recentBlockhash = (
+      await providerEphemeralRollup.connection.getLatestBlockhash()
+    ).blockhash;
+    tx = await wallet.signTransaction(tx);
+
+    try {
+      await providerEphemeralRollup.connection.sendRawTransaction(
+        tx.serialize()
+      );
+      assert.fail("process_auction should require every bid");
+    } catch (e) {
+      const logs = (e.logs ?? []).join("\n");
+      assert.include(logs + e.toString(), "MissingBids");
+    }
+
+    const info = await providerEphemeralRollup.connection.getAccountInfo(
+      record
+    );
+    const recordAccount = program.coder.accounts.decode(
+      "auctionRecord",
+      info.data
+    );
+    assert.deepEqual(recordAccount.auctionState, { delegated: {} });
+  });
+
+  it("Keeps the auction record delegated until the auction has run", async function () {
+    this.timeout(120000);
+
+    // Skip if ephemeral rollup not connected
+    if (!ephemeralRollupConnected) {
+      console.warn("⚠️ Skipping early undelegation test: Ephemeral Rollup not connected");
+      this.skip();
+    }
+
+    const { id, request, record } = await placeStandaloneAsk(requestTtl);
+    assert.isTrue(await delegateAdRequest(request, id));
+    assert.isTrue(await delegateAuctionRecord(id, record));
+
+    const undelegateRecord = () =>
+      program.methods
+        .undelegateAuctionRecordAfterAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          publisher: publisherOwner.publicKey,
+          auctionRecord: record,
+        })
+        .transaction();
+
+    // Handing the record back before process_auction would strand it in Delegated
+    let tx = await undelegateRecord();
+    tx.feePayer = wallet.publicKey;
+    tx.recentBlockhash = (
+      await providerEphemeralRollup.connection.getLatestBlockhash()
+    ).blockhash;
+    tx = await wallet.signTransaction(tx);
+
+    try {
+      await providerEphemeralRollup.connection.sendRawTransaction(
+        tx.serialize()
+      );
+      assert.fail("Record undelegation should wait for the auction");
+    } catch (e) {
+      const logs = (e.logs ?? []).join("\n");
+      assert.include(logs + e.toString(), "AuctionNotCleared");
+    }
+
+    // The auction still runs in the rollup, with no bids it ends as a no-fill
+    await sendToEphemeralRollup(
+      await program.methods
+        .processAuction(id)
+        .accountsPartial({
+          authority: authority.publicKey,
+          operator: null,
+          adRequest: request,
+          publisher: publisherState,
+          adwConfig,
+          auctionRecord: record,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .transaction()
+    );
+
+    const info = await providerEphemeralRollup.connection.getAccountInfo(
+      record
+    );
+    const recordAccount = program.coder.accounts.decode(
+      "auctionRecord",
+      info.data
+    );
+    assert.deepEqual(recordAccount.auctionState, { noFill: {} });
+
+    // Once the auction has run the record can leave the rollup
+    await sendToEphemeralRollup(await undelegateRecord());
+  });
+
+  it("Rejects bids past the per-request limit", async function () {
+    this.timeout(120000);
+
+    // Should match MAX_BIDS_PER_REQUEST in constants.rs
+    const maxBids = 20;
+
+    // A one unit floor keeps the locked bids negligible, they stay on the open request
+    await depositDsp1(new BN(maxBids + 1));
+    const { id, request } = await placeStandaloneAsk(
+      requestTtl,
+      { firstPrice: {} },
+      new BN(1)
+    );
+
+    for (let i = 0; i < maxBids; i++) {
+      await placeStandaloneBid(id, request, new BN(1));
+    }
+    assert.equal(
+      (await program.account.adRequest.fetch(request)).bidCount,
+      maxBids
+    );
+
+    try {
+      await placeStandaloneBid(id, request, new BN(1));
+      assert.fail("Bid past the limit should be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "TooManyBids");
+    }
+
+    assert.equal(
+      (await program.account.adRequest.fetch(request)).bidCount,
+      maxBids
+    );
+  });
+
   // Optional: Cleanup function after all tests
   after(async function () {
     console.log("Tests completed!");